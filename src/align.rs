@@ -0,0 +1,138 @@
+//! Aligns the cues of a subtitle to the cues of a correctly timed reference subtitle, using only
+//! their timings. Every target cue gets an offset, and the offsets are chosen to maximize the
+//! total overlap of the shifted target cues with the reference cues. The offset can change
+//! between two cues (a split, e.g. for a commercial break that only one of the releases has), but
+//! each split has to improve the overlap by at least the split penalty.
+
+/// The start and end of a cue in milliseconds.
+pub type Cue = (i64, i64);
+
+/// The offsets that are tried are multiples of this many milliseconds.
+pub const STEP: i64 = 40;
+
+#[derive(Debug, Clone, Copy)]
+pub struct AlignOptions {
+    /// The largest offset in milliseconds that is tried in either direction.
+    pub max_offset: i64,
+    /// How much a split must improve the overlap in milliseconds. `None` disables splits.
+    pub split_penalty: Option<i64>,
+}
+
+/// Returns the offset in milliseconds for each cue of `target`. The target cues must be sorted by
+/// start, the reference cues may be in any order.
+pub fn align(reference: &[Cue], target: &[Cue], options: AlignOptions) -> Vec<i64> {
+    if target.is_empty() {
+        return Vec::new();
+    }
+    let mut reference = reference
+        .iter()
+        .copied()
+        .filter(|(start, end)| end > start)
+        .collect::<Vec<_>>();
+    reference.sort();
+    let longest = reference
+        .iter()
+        .map(|(start, end)| end - start)
+        .max()
+        .unwrap_or(0);
+
+    let steps = options.max_offset.max(0) / STEP;
+    let offsets = (-steps..=steps).map(|i| i * STEP).collect::<Vec<_>>();
+
+    // total[d] is the best overlap of the cues so far if the current cue has offset d. split[i]
+    // tells for each offset if cue i starts a new segment, whose previous cue has the offset with
+    // the best total (best[i - 1]).
+    let mut total = vec![0; offsets.len()];
+    let mut split = Vec::with_capacity(target.len());
+    let mut best = Vec::with_capacity(target.len());
+    for (i, &cue) in target.iter().enumerate() {
+        let scores = overlaps(&reference, longest, cue, &offsets);
+        let mut splits = vec![false; offsets.len()];
+        if i == 0 {
+            total = scores;
+        } else {
+            let previous_best = total[best[i - 1]];
+            for d in 0..offsets.len() {
+                let continued = total[d];
+                total[d] = scores[d]
+                    + match options.split_penalty {
+                        Some(penalty) if previous_best - penalty > continued => {
+                            splits[d] = true;
+                            previous_best - penalty
+                        }
+                        _ => continued,
+                    };
+            }
+        }
+        split.push(splits);
+        best.push(best_offset(&total, &offsets));
+    }
+
+    let mut result = vec![0; target.len()];
+    let mut d = best[target.len() - 1];
+    for i in (0..target.len()).rev() {
+        result[i] = offsets[d];
+        if split[i][d] {
+            d = best[i - 1];
+        }
+    }
+
+    // The best offset of each segment is somewhere around the step that was found.
+    let mut first = 0;
+    while first < target.len() {
+        let last = (first..target.len())
+            .find(|&i| result[i] != result[first])
+            .unwrap_or(target.len());
+        let offset = refine(&reference, longest, &target[first..last], result[first]);
+        result[first..last].fill(offset);
+        first = last;
+    }
+    result
+}
+
+/// Finds the offset within one step of `offset` that has the largest overlap, to the millisecond.
+fn refine(reference: &[Cue], longest: i64, cues: &[Cue], offset: i64) -> i64 {
+    (offset - STEP..=offset + STEP)
+        .max_by_key(|&candidate| {
+            let total = cues
+                .iter()
+                .map(|&cue| overlaps(reference, longest, cue, &[candidate])[0])
+                .sum::<i64>();
+            (total, -(candidate - offset).abs())
+        })
+        .unwrap()
+}
+
+/// The overlap in milliseconds of `cue` with the reference cues for each offset.
+fn overlaps(reference: &[Cue], longest: i64, cue: Cue, offsets: &[i64]) -> Vec<i64> {
+    let mut scores = vec![0; offsets.len()];
+    let (Some(&min_offset), Some(&max_offset)) = (offsets.first(), offsets.last()) else {
+        return scores;
+    };
+    // Only reference cues that start close enough can overlap the shifted cue.
+    let first = reference.partition_point(|&(start, _)| start < cue.0 + min_offset - longest);
+    let last = reference.partition_point(|&(start, _)| start < cue.1 + max_offset);
+    for &(start, end) in &reference[first..last] {
+        // The cue overlaps this reference cue for offsets between start - cue end and
+        // end - cue start.
+        let from = (start - cue.1 - min_offset).div_euclid(STEP).max(0);
+        let to = (end - cue.0 - min_offset)
+            .div_euclid(STEP)
+            .min(offsets.len() as i64 - 1);
+        if to < from {
+            continue;
+        }
+        for d in from as usize..=to as usize {
+            let offset = offsets[d];
+            scores[d] += ((cue.1 + offset).min(end) - (cue.0 + offset).max(start)).max(0);
+        }
+    }
+    scores
+}
+
+/// The index of the offset with the best total. Ties go to the offset closest to zero.
+fn best_offset(total: &[i64], offsets: &[i64]) -> usize {
+    (0..total.len())
+        .max_by_key(|&d| (total[d], -offsets[d].abs()))
+        .unwrap()
+}
@@ -0,0 +1,234 @@
+//! The external programs that the alass command can align subtitles to their video with. Each
+//! backend knows how to find its binary, how to build its command line for a subtitle/video
+//! match and how to read the result of a run.
+
+use crate::config::AlignBackend;
+use anyhow::Result as AnyResult;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+static ALASS_BINARY_NAMES: &[&str] = &["alass-cli", "alass"];
+static FFSUBSYNC_BINARY_NAMES: &[&str] = &["ffsubsync", "ffs"];
+
+pub trait Aligner: Sync {
+    /// The name of the backend, used in messages.
+    fn name(&self) -> &str;
+
+    /// Finds the binary of the backend.
+    fn binary(&self) -> AnyResult<PathBuf>;
+
+    /// Builds the command that aligns `sub_path` to `video_path` and writes the result to
+    /// `out_path`. `flags` are the extra arguments given by the user.
+    fn command(
+        &self,
+        binary: &Path,
+        video_path: &Path,
+        sub_path: &Path,
+        out_path: &Path,
+        flags: &[String],
+    ) -> Command;
+
+    /// Checks the output of a finished run. Returns a short summary of the alignment if the
+    /// backend reports one.
+    fn parse_result(&self, output: &Output) -> AnyResult<Option<String>> {
+        if !output.status.success() {
+            bail!("{} failed ({})", self.name(), output.status);
+        }
+        Ok(None)
+    }
+}
+
+pub fn aligner(backend: &AlignBackend) -> Box<dyn Aligner> {
+    match backend {
+        AlignBackend::Alass => Box::new(Alass),
+        AlignBackend::Ffsubsync => Box::new(Ffsubsync),
+        AlignBackend::Template(template) => Box::new(Template {
+            template: template.clone(),
+        }),
+    }
+}
+
+/// https://github.com/kaegi/alass
+struct Alass;
+
+impl Aligner for Alass {
+    fn name(&self) -> &str {
+        "alass"
+    }
+
+    fn binary(&self) -> AnyResult<PathBuf> {
+        find_binary(ALASS_BINARY_NAMES, "https://github.com/kaegi/alass")
+    }
+
+    fn command(
+        &self,
+        binary: &Path,
+        video_path: &Path,
+        sub_path: &Path,
+        out_path: &Path,
+        flags: &[String],
+    ) -> Command {
+        let mut cmd = Command::new(binary);
+        cmd.arg(video_path).arg(sub_path).arg(out_path).args(flags);
+        cmd
+    }
+
+    /// alass prints a line for each block of subtitles that it shifted by the same amount, e.g.
+    /// `shifted block of 120 subtitles with length 0:45:12.000 by -0:00:01.230`. More than one
+    /// block means that it split the subtitle.
+    fn parse_result(&self, output: &Output) -> AnyResult<Option<String>> {
+        if !output.status.success() {
+            bail!("alass failed ({})", output.status);
+        }
+        let log = String::from_utf8_lossy(&output.stdout);
+        let blocks = log
+            .lines()
+            .filter_map(|line| {
+                let (_, rest) = line.split_once("shifted block of ")?;
+                let (count, rest) = rest.split_once(" subtitles")?;
+                let (_, delta) = rest.rsplit_once(" by ")?;
+                Some((count.trim().parse::<usize>().ok()?, msecs(delta.trim())?))
+            })
+            .collect::<Vec<_>>();
+        Ok(match blocks.as_slice() {
+            [] => None,
+            [(_, delta)] => Some(format!("offset {:.3}s", *delta as f64 / 1000.0)),
+            _ => Some(
+                blocks
+                    .iter()
+                    .map(|(count, delta)| {
+                        format!("offset {:.3}s for {} cues", *delta as f64 / 1000.0, count)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+        })
+    }
+}
+
+/// Parses a time printed by alass, e.g. `-0:00:01.230`, as milliseconds.
+fn msecs(time: &str) -> Option<i64> {
+    let (sign, time) = match time.strip_prefix('-') {
+        Some(time) => (-1, time),
+        None => (1, time),
+    };
+    let mut parts = time.split(':');
+    let (hours, mins, secs) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() {
+        return None;
+    }
+    let secs = secs.parse::<f64>().ok()?;
+    let total = hours.parse::<i64>().ok()? * 3_600_000
+        + mins.parse::<i64>().ok()? * 60_000
+        + (secs * 1000.0).round() as i64;
+    Some(sign * total)
+}
+
+/// https://github.com/smacke/ffsubsync
+struct Ffsubsync;
+
+impl Aligner for Ffsubsync {
+    fn name(&self) -> &str {
+        "ffsubsync"
+    }
+
+    fn binary(&self) -> AnyResult<PathBuf> {
+        find_binary(
+            FFSUBSYNC_BINARY_NAMES,
+            "https://github.com/smacke/ffsubsync",
+        )
+    }
+
+    fn command(
+        &self,
+        binary: &Path,
+        video_path: &Path,
+        sub_path: &Path,
+        out_path: &Path,
+        flags: &[String],
+    ) -> Command {
+        let mut cmd = Command::new(binary);
+        cmd.arg(video_path)
+            .arg("-i")
+            .arg(sub_path)
+            .arg("-o")
+            .arg(out_path)
+            .args(flags);
+        cmd
+    }
+
+    /// ffsubsync logs the offset and frame rate factor it found, e.g. `offset seconds: 1.230`
+    /// and `framerate scale factor: 1.000`.
+    fn parse_result(&self, output: &Output) -> AnyResult<Option<String>> {
+        if !output.status.success() {
+            bail!("ffsubsync failed ({})", output.status);
+        }
+        let log = String::from_utf8_lossy(&output.stderr);
+        let value = |key: &str| {
+            log.lines()
+                .filter_map(|line| line.split_once(key))
+                .filter_map(|(_, value)| value.trim().parse::<f64>().ok())
+                .next_back()
+        };
+        Ok(
+            match (value("offset seconds:"), value("framerate scale factor:")) {
+                (Some(offset), Some(scale)) => {
+                    Some(format!("offset {:.3}s, scale {:.3}", offset, scale))
+                }
+                (Some(offset), None) => Some(format!("offset {:.3}s", offset)),
+                _ => None,
+            },
+        )
+    }
+}
+
+/// A user given command line. `{video}`, `{sub}` and `{out}` in the arguments are replaced with
+/// the paths of the video, the subtitle and the file that the aligned subtitle must be written to.
+struct Template {
+    template: Vec<String>,
+}
+
+impl Aligner for Template {
+    fn name(&self) -> &str {
+        &self.template[0]
+    }
+
+    fn binary(&self) -> AnyResult<PathBuf> {
+        which::which(&self.template[0])
+            .map_err(|_| anyhow!("could not find {:?} in PATH", self.template[0]))
+    }
+
+    fn command(
+        &self,
+        binary: &Path,
+        video_path: &Path,
+        sub_path: &Path,
+        out_path: &Path,
+        flags: &[String],
+    ) -> Command {
+        let mut cmd = Command::new(binary);
+        for arg in &self.template[1..] {
+            cmd.arg(
+                arg.replace("{video}", &video_path.to_string_lossy())
+                    .replace("{sub}", &sub_path.to_string_lossy())
+                    .replace("{out}", &out_path.to_string_lossy()),
+            );
+        }
+        cmd.args(flags);
+        cmd
+    }
+}
+
+fn find_binary(names: &[&str], url: &str) -> AnyResult<PathBuf> {
+    for bin in names {
+        if let Ok(bin) = which::which(bin) {
+            return Ok(bin);
+        }
+    }
+    bail!(
+        "could not find any of the following in PATH: {}\n\
+        See {} for install instructions.",
+        names.join(", "),
+        url
+    )
+}
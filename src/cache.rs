@@ -0,0 +1,152 @@
+//! Remembers the subtitle/video pairs that the alass command aligned, so that pairs that haven't
+//! changed since their last successful alignment aren't aligned again.
+//!
+//! The cache is a text file with a line for each subtitle. A pair is unchanged if the video has
+//! the same size, modification time and partial hash, the subtitle and its aligned output have the
+//! same content as after the alignment, and the same aligner and flags are used.
+
+use crate::subtitle::atomic;
+use anyhow::Result as AnyResult;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// The name of the cache file in the target directory.
+pub const FILE_NAME: &str = ".sub-batch-alignments";
+
+const HEADER: &str = "# sub-batch alignment cache v1";
+
+/// How many bytes are hashed at the start and at the end of a video.
+const VIDEO_HASH_BYTES: u64 = 64 * 1024;
+
+/// The state of a pair after its alignment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// The size, modification time and partial hash of the video.
+    pub video: String,
+    /// The aligner and the flags it was run with.
+    pub aligner: String,
+    pub sub_hash: u64,
+    pub out_path: PathBuf,
+    pub out_hash: u64,
+}
+
+pub struct AlignmentCache {
+    path: PathBuf,
+    /// The entry and the alignment summary for each subtitle.
+    entries: BTreeMap<PathBuf, (Entry, String)>,
+}
+
+impl AlignmentCache {
+    /// Reads the cache file at `path`. A missing file is an empty cache, and lines that can't be
+    /// read are ignored.
+    pub fn load(path: &Path) -> AnyResult<Self> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => bail!("failed to read the alignment cache {:?}: {}", path, e),
+        };
+        let entries = content
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(parse_line)
+            .collect();
+        Ok(AlignmentCache {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    /// Returns the summary of the last alignment of `sub` if it was made in the state `entry`.
+    pub fn lookup(&self, sub: &Path, entry: &Entry) -> Option<&str> {
+        match self.entries.get(sub) {
+            Some((cached, summary)) if cached == entry => Some(summary),
+            _ => None,
+        }
+    }
+
+    pub fn insert(&mut self, sub: PathBuf, entry: Entry, summary: String) {
+        self.entries.insert(sub, (entry, summary));
+    }
+
+    pub fn save(&self) -> AnyResult<()> {
+        let mut content = format!("{}\n", HEADER);
+        for (sub, (entry, summary)) in &self.entries {
+            let fields = [
+                &*sub.to_string_lossy(),
+                &entry.video,
+                &entry.aligner,
+                &format!("{:016x}", entry.sub_hash),
+                &entry.out_path.to_string_lossy(),
+                &format!("{:016x}", entry.out_hash),
+                summary,
+            ];
+            // The fields are separated by tabs, so values that contain tabs or newlines can't
+            // be cached.
+            if fields.iter().any(|field| field.contains(['\t', '\n'])) {
+                continue;
+            }
+            content.push_str(&fields.join("\t"));
+            content.push('\n');
+        }
+        atomic::write_file(&self.path, content.as_bytes(), None)
+            .map_err(|e| anyhow!("failed to write the alignment cache {:?}: {}", self.path, e))
+    }
+}
+
+fn parse_line(line: &str) -> Option<(PathBuf, (Entry, String))> {
+    let fields = line.split('\t').collect::<Vec<_>>();
+    let [sub, video, aligner, sub_hash, out_path, out_hash, summary] = fields.as_slice() else {
+        return None;
+    };
+    let entry = Entry {
+        video: video.to_string(),
+        aligner: aligner.to_string(),
+        sub_hash: u64::from_str_radix(sub_hash, 16).ok()?,
+        out_path: PathBuf::from(out_path),
+        out_hash: u64::from_str_radix(out_hash, 16).ok()?,
+    };
+    Some((PathBuf::from(sub), (entry, summary.to_string())))
+}
+
+/// Identifies a video by its size, modification time and a hash of its start and end. This is
+/// much faster than hashing the whole video and still notices when it is replaced.
+pub fn video_id(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let metadata = file.metadata()?;
+    let size = metadata.len();
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    let mut buf = Vec::new();
+    (&mut file).take(VIDEO_HASH_BYTES).read_to_end(&mut buf)?;
+    if size > 2 * VIDEO_HASH_BYTES {
+        file.seek(SeekFrom::End(-(VIDEO_HASH_BYTES as i64)))?;
+    }
+    file.read_to_end(&mut buf)?;
+
+    Ok(format!(
+        "{}:{}.{:09}:{:016x}",
+        size,
+        modified.as_secs(),
+        modified.subsec_nanos(),
+        hash(&buf)
+    ))
+}
+
+/// The hash of the content of a file.
+pub fn file_hash(path: &Path) -> io::Result<u64> {
+    Ok(hash(&fs::read(path)?))
+}
+
+/// 64-bit FNV-1a, which unlike the hashers of the standard library is stable across Rust
+/// versions, so the hashes can be stored.
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
@@ -1,15 +1,24 @@
+use crate::aligner::{self, Aligner};
+use crate::cache::{self, AlignmentCache, Entry};
 use crate::commands::util;
-use crate::commands::util::AskMatchAnswer;
+use crate::commands::util::{AskMatchAnswer, BatchReport, PartialSuccess};
 use crate::config::{AlassConfig, GlobalConfig};
+use crate::jobs;
 use crate::scanner;
 use crate::scanner::{MatchInfo, ScanOptions};
+use crate::subtitle::atomic;
 use anyhow::Result as AnyResult;
 use rayon::prelude::*;
 use rustyline::DefaultEditor;
+use std::fs;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Output};
+use std::time::Duration;
 
-static ALASS_BINARY_NAMES: &[&str] = &["alass-cli", "alass"];
+/// The directory in the target path that the output of the aligner is logged to, if no
+/// --log-dir is given.
+const LOG_DIR: &str = ".sub-batch-logs";
 
 pub struct AlassCommand<'a> {
     global_conf: &'a GlobalConfig,
@@ -44,11 +53,21 @@ impl<'a> AlassCommand<'a> {
             &self.conf.match_config,
         ))?;
 
-        util::validate_sub_and_file_matches(self.global_conf, &matches)?;
+        let mut report = BatchReport::default();
+        let matches = util::validate_sub_and_file_matches(
+            self.global_conf,
+            matches,
+            util::SUBPARSE_SUPPORTED_SUBTITLE_FORMATS,
+            &mut report,
+        )?;
+
+        let mismatches =
+            util::duration_mismatches(&matches, self.conf.match_config.duration_check.as_ref());
+        util::check_durations(self.global_conf, &matches, &mismatches)?;
 
         if !self.global_conf.confirm {
-            self.align_all(&matches)?;
-            return Ok(());
+            self.align_all(&matches, &mut report)?;
+            return report.finish();
         }
 
         let match_ok_answer = util::ask_match_is_ok(
@@ -57,11 +76,15 @@ impl<'a> AlassCommand<'a> {
             self.conf.match_config.video_area.as_ref(),
             self.global_conf.color,
             true,
+            &mismatches,
             self.line_editor.as_mut(),
         )?;
 
         match match_ok_answer {
-            AskMatchAnswer::Yes => self.align_all(&matches)?,
+            AskMatchAnswer::Yes => {
+                self.align_all(&matches, &mut report)?;
+                return report.finish();
+            }
             AskMatchAnswer::EditSubtitleRegex => loop {
                 match util::get_user_regex(
                     "enter new subtitle area regex: ",
@@ -70,7 +93,7 @@ impl<'a> AlassCommand<'a> {
                     Ok(Some(regex)) => {
                         let mut new_conf = self.conf.clone();
                         new_conf.match_config.sub_area = Some(regex);
-                        if self.run_again(new_conf) {
+                        if self.run_again(new_conf)? {
                             break;
                         }
                     }
@@ -86,7 +109,7 @@ impl<'a> AlassCommand<'a> {
                     Ok(Some(regex)) => {
                         let mut new_conf = self.conf.clone();
                         new_conf.match_config.video_area = Some(regex);
-                        if self.run_again(new_conf) {
+                        if self.run_again(new_conf)? {
                             break;
                         }
                     }
@@ -99,59 +122,326 @@ impl<'a> AlassCommand<'a> {
         Ok(())
     }
 
-    fn run_again(&mut self, new_conf: AlassConfig) -> bool {
-        if let Err(e) =
-            AlassCommand::new_with_editor(self.global_conf, new_conf, self.line_editor.take()).run()
+    fn run_again(&mut self, new_conf: AlassConfig) -> AnyResult<bool> {
+        match AlassCommand::new_with_editor(self.global_conf, new_conf, self.line_editor.take())
+            .run()
         {
-            println!("error: {}", e);
-            false
-        } else {
-            true
+            Ok(()) => Ok(true),
+            // The run finished but skipped some subs, so we are done.
+            Err(e) if e.is::<PartialSuccess>() => Err(e),
+            Err(e) => {
+                println!("error: {}", e);
+                Ok(false)
+            }
         }
     }
 
-    fn align_all(&self, aligns: &[MatchInfo]) -> AnyResult<()> {
-        let alass_binary = alass_binary()?;
+    /// Aligns all subs, also when some of them fail, and prints a table of the results. Pairs
+    /// that are unchanged since their last successful alignment are skipped unless --force is
+    /// given. Fails if all alignments failed, or any in `--strict` mode.
+    fn align_all(&self, aligns: &[MatchInfo], report: &mut BatchReport) -> AnyResult<()> {
+        let mut cache = AlignmentCache::load(&self.global_conf.path.join(cache::FILE_NAME))?;
+        let aligner_key = format!("{:?} {:?}", self.conf.backend, self.conf.flags);
+        let cached = aligns
+            .iter()
+            .map(|m| {
+                if self.conf.force {
+                    return None;
+                }
+                let entry = self.cache_entry(m, &aligner_key).ok()?;
+                cache
+                    .lookup(&self.cache_path(&m.sub_path), &entry)
+                    .map(String::from)
+            })
+            .collect::<Vec<_>>();
+        let to_align = aligns
+            .iter()
+            .zip(&cached)
+            .filter(|(_, cached)| cached.is_none())
+            .map(|(m, _)| m)
+            .collect::<Vec<_>>();
 
-        if self.conf.no_parallel {
-            for m in aligns {
-                self.align(&alass_binary, &m.sub_path, &m.video_path)?;
-            }
+        let mut outcomes = if to_align.is_empty() {
+            Vec::new()
         } else {
-            aligns
-                .par_iter()
-                .try_for_each(|m| self.align(&alass_binary, &m.sub_path, &m.video_path))?;
+            self.run_aligner(&to_align)?
+        }
+        .into_iter();
+        let outcomes = cached
+            .into_iter()
+            .map(|cached| match cached {
+                Some(summary) => Outcome::unchanged(summary),
+                None => outcomes.next().unwrap(),
+            })
+            .collect::<Vec<_>>();
+
+        let rows = aligns
+            .iter()
+            .zip(&outcomes)
+            .map(|(m, outcome)| {
+                vec![
+                    match &outcome.result {
+                        _ if outcome.unchanged => "unchanged",
+                        Ok(_) => "ok",
+                        Err(_) => "failed",
+                    }
+                    .to_string(),
+                    outcome
+                        .exit_code
+                        .map(|code| code.to_string())
+                        .unwrap_or("-".to_string()),
+                    m.sub_file_name.clone(),
+                    m.video_file_name.clone(),
+                    match &outcome.result {
+                        Ok(Some(summary)) => summary.clone(),
+                        _ => String::new(),
+                    },
+                    outcome
+                        .log
+                        .as_ref()
+                        .map(|log| log.display().to_string())
+                        .unwrap_or_default(),
+                    match &outcome.result {
+                        Ok(_) => String::new(),
+                        Err(e) => e.to_string(),
+                    },
+                ]
+            })
+            .collect::<Vec<_>>();
+        print!(
+            "\n{}",
+            util::table(
+                &[
+                    "RESULT",
+                    "EXIT",
+                    "SUBTITLE",
+                    "VIDEO",
+                    "ALIGNMENT",
+                    "LOG",
+                    "ERROR"
+                ],
+                &rows
+            )
+        );
+        let unchanged = aligns.len() - to_align.len();
+        if unchanged > 0 {
+            println!(
+                "{} pair(s) are unchanged since their last alignment, use --force to align them \
+                again",
+                unchanged
+            );
+        }
+
+        let mut aligned = 0;
+        for (m, outcome) in aligns.iter().zip(&outcomes) {
+            if let (false, Ok(summary)) = (outcome.unchanged, &outcome.result)
+                && let Ok(entry) = self.cache_entry(m, &aligner_key)
+            {
+                cache.insert(
+                    self.cache_path(&m.sub_path),
+                    entry,
+                    summary.clone().unwrap_or_default(),
+                );
+                aligned += 1;
+            }
+        }
+        if aligned > 0
+            && let Err(e) = cache.save()
+        {
+            eprintln!("warning: {}", e);
         }
+
+        let failed = outcomes.iter().filter(|o| o.result.is_err()).count();
+        if failed > 0 && failed == to_align.len() {
+            bail!("all {} alignments failed", failed);
+        }
+        if failed > 0 && self.global_conf.strict {
+            bail!("{} of {} alignments failed", failed, to_align.len());
+        }
+        report.processed += to_align.len() - failed;
+        report.failed += failed;
         Ok(())
     }
 
-    fn align(&self, alass_binary: &Path, sub_path: &Path, video_path: &Path) -> AnyResult<()> {
-        let mut cmd = Command::new(alass_binary);
+    /// Runs the aligner for each pair, in parallel unless --jobs is 1.
+    fn run_aligner(&self, aligns: &[&MatchInfo]) -> AnyResult<Vec<Outcome>> {
+        let aligner = aligner::aligner(&self.conf.backend);
+        let binary = aligner.binary()?;
+        let log_dir = self
+            .conf
+            .log_dir
+            .clone()
+            .unwrap_or_else(|| self.global_conf.path.join(LOG_DIR));
+        fs::create_dir_all(&log_dir)
+            .map_err(|e| anyhow!("failed to create the log directory {:?}: {}", log_dir, e))?;
 
-        cmd.arg(video_path).arg(sub_path).arg(sub_path);
+        Ok(if self.conf.jobs == Some(1) {
+            aligns
+                .iter()
+                .map(|m| self.align(aligner.as_ref(), &binary, &log_dir, m))
+                .collect()
+        } else {
+            // A pool of its own so that --jobs limits only the aligner runs. 0 threads means one
+            // per CPU.
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(self.conf.jobs.unwrap_or(0))
+                .build()?
+                .install(|| {
+                    aligns
+                        .par_iter()
+                        .map(|m| self.align(aligner.as_ref(), &binary, &log_dir, m))
+                        .collect()
+                })
+        })
+    }
+
+    /// The current state of a pair, which is compared with the state after its last alignment.
+    fn cache_entry(&self, m: &MatchInfo, aligner: &str) -> io::Result<Entry> {
+        let out_path = self.out_path(&m.sub_path);
+        Ok(Entry {
+            video: cache::video_id(&m.video_path)?,
+            aligner: aligner.to_string(),
+            sub_hash: cache::file_hash(&m.sub_path)?,
+            out_hash: cache::file_hash(&out_path)?,
+            out_path: self.cache_path(&out_path),
+        })
+    }
 
-        if !self.conf.flags.is_empty() {
-            cmd.args(&self.conf.flags);
+    /// How `path` is stored in the cache: relative to the target directory, so that it doesn't
+    /// matter how --path is given, or as an absolute path if it is outside of it.
+    fn cache_path(&self, path: &Path) -> PathBuf {
+        match path.strip_prefix(&self.global_conf.path) {
+            Ok(relative) => relative.to_path_buf(),
+            Err(_) => std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()),
         }
+    }
+
+    /// The file that the aligned version of the sub at `sub_path` is written to. This is the sub
+    /// itself unless --suffix or --out-dir is given.
+    fn out_path(&self, sub_path: &Path) -> PathBuf {
+        let out_path = match &self.conf.suffix {
+            Some(suffix) => with_suffix(sub_path, suffix),
+            None => sub_path.to_path_buf(),
+        };
+        util::output_path(self.global_conf, &out_path)
+    }
 
-        let exit_code = cmd.current_dir(&self.global_conf.path).spawn()?.wait()?;
+    fn align(
+        &self,
+        aligner: &dyn Aligner,
+        binary: &Path,
+        log_dir: &Path,
+        m: &MatchInfo,
+    ) -> Outcome {
+        // The aligner writes to a temporary file that replaces the output once the aligner has
+        // succeeded, so the output is never left half written.
+        let out_path = self.out_path(&m.sub_path);
+        let output = match atomic::temp_file_for(&out_path) {
+            Ok(output) => output,
+            Err(e) => return Outcome::failed(e),
+        };
 
-        if !exit_code.success() {
-            bail!("sub-batch: `{:?}` failed", alass_binary);
+        // The aligner runs in the target directory, so it gets absolute paths.
+        let paths = [m.video_path.as_path(), &m.sub_path, output.path()]
+            .map(|path| std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()));
+        let [video_path, sub_path, temp_path] = &paths;
+        let mut cmd = aligner.command(binary, video_path, sub_path, temp_path, &self.conf.flags);
+        cmd.current_dir(&self.global_conf.path);
+        // The output is collected so that the output of parallel runs isn't mixed up. The run is
+        // tracked so that it is killed and its output removed if sub-batch is interrupted.
+        let timeout = self
+            .conf
+            .timeout
+            .map(|timeout| Duration::from_millis(timeout as u64));
+        let (result, timed_out) = match jobs::run(&mut cmd, timeout, output.path()) {
+            Ok(finished) => (finished.output, finished.timed_out),
+            Err(e) => return Outcome::failed(anyhow!("failed to run {}: {}", aligner.name(), e)),
+        };
+        let log = log_dir.join(format!("{}.log", m.sub_file_name));
+        let log = match write_log(&log, &cmd, &result) {
+            Ok(()) => Some(log),
+            Err(e) => {
+                eprintln!("warning: failed to write {:?}: {}", log, e);
+                None
+            }
+        };
+        let _ = io::stdout().write_all(&result.stdout);
+        if self.global_conf.verbose {
+            let _ = io::stderr().write_all(&result.stderr);
+        }
+
+        let summary = if timed_out {
+            Err(anyhow!(
+                "{} timed out after {}",
+                aligner.name(),
+                util::timestamp(self.conf.timeout.unwrap_or_default())
+            ))
+        } else {
+            aligner.parse_result(&result)
+        };
+        let summary = summary.and_then(|summary| {
+            atomic::replace_file(&out_path, output, self.global_conf.backup.as_deref())?;
+            Ok(summary)
+        });
+        Outcome {
+            exit_code: result.status.code(),
+            log,
+            result: summary,
+            unchanged: false,
         }
-        Ok(())
     }
 }
 
-fn alass_binary() -> AnyResult<PathBuf> {
-    for bin in ALASS_BINARY_NAMES {
-        if let Ok(bin) = which::which(bin) {
-            return Ok(bin);
+/// The result of aligning one sub.
+struct Outcome {
+    /// The exit code of the aligner, `None` if it didn't run or was killed by a signal.
+    exit_code: Option<i32>,
+    /// The file that the command line and output of the aligner were written to.
+    log: Option<PathBuf>,
+    /// A short summary of the alignment if the aligner reported one.
+    result: AnyResult<Option<String>>,
+    /// Set if the pair wasn't aligned because it is unchanged since its last alignment.
+    unchanged: bool,
+}
+
+impl Outcome {
+    fn failed(error: anyhow::Error) -> Self {
+        Outcome {
+            exit_code: None,
+            log: None,
+            result: Err(error),
+            unchanged: false,
+        }
+    }
+
+    fn unchanged(summary: String) -> Self {
+        Outcome {
+            exit_code: None,
+            log: None,
+            result: Ok(Some(summary).filter(|summary| !summary.is_empty())),
+            unchanged: true,
         }
     }
-    bail!(
-        "could not find any of the following in PATH: {}\n\
-        See https://github.com/kaegi/alass for install instructions.",
-        ALASS_BINARY_NAMES.join(", ")
-    )
+}
+
+/// Inserts `suffix` before the extension of the file name, e.g. ep01.srt with the suffix .synced
+/// is ep01.synced.srt.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    if let Some(ext) = path.extension() {
+        name.push(".");
+        name.push(ext);
+    }
+    path.with_file_name(name)
+}
+
+fn write_log(path: &Path, cmd: &Command, output: &Output) -> io::Result<()> {
+    let mut log = fs::File::create(path)?;
+    writeln!(log, "$ {:?}\n{}\n", cmd, output.status)?;
+    log.write_all(b"stdout:\n")?;
+    log.write_all(&output.stdout)?;
+    log.write_all(b"\nstderr:\n")?;
+    log.write_all(&output.stderr)?;
+    Ok(())
 }
@@ -0,0 +1,158 @@
+use crate::commands::util;
+use crate::commands::util::BatchReport;
+use crate::config::{CleanConfig, CleanRule, GlobalConfig};
+use crate::scanner::{self, AreaScan, ScanOptions, SecondaryExtensionPolicy};
+use crate::subtitle::{Format, ParsedSub};
+use anyhow::Result as AnyResult;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use subparse::SubtitleFormat;
+
+static SDH: Lazy<Regex> = Lazy::new(|| Regex::new(r"\[[^\]]*\]|\([^)]*\)").unwrap());
+
+// An upper case speaker label at the start of the line, possibly after a dialogue dash and tags.
+static SPEAKER: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^((?:\s*-\s*)?(?:<[^>]*>|\{[^}]*\})*(?:\s*-\s*)?)\p{Lu}[\p{Lu}\d .'-]*:\s*")
+        .unwrap()
+});
+
+static TAGS: Lazy<Regex> = Lazy::new(|| Regex::new(r"<[^>]*>|\{\\[^}]*\}").unwrap());
+
+static SPACES: Lazy<Regex> = Lazy::new(|| Regex::new(r"\s{2,}").unwrap());
+
+static MUSIC: Lazy<Regex> = Lazy::new(|| Regex::new(r"[♪♫]").unwrap());
+
+pub struct CleanCommand<'a> {
+    global_conf: &'a GlobalConfig,
+    conf: CleanConfig,
+}
+
+impl<'a> CleanCommand<'a> {
+    pub fn new(global_conf: &'a GlobalConfig, conf: CleanConfig) -> Self {
+        CleanCommand { global_conf, conf }
+    }
+
+    pub fn run(&self) -> AnyResult<()> {
+        let mut report = BatchReport::default();
+        let matches = scanner::scan_subs_only(ScanOptions::from_global_conf(
+            self.global_conf,
+            None,
+            AreaScan::Normal,
+            None,
+            AreaScan::Normal,
+            SecondaryExtensionPolicy::Never,
+        ))?;
+        let matches = util::validate_sub_matches(self.global_conf, matches, &mut report)?;
+
+        let mut parsed_subs = util::read_subs(
+            self.global_conf,
+            &matches,
+            &mut report,
+            self.conf.output_encoding,
+            |path| {
+                let sub = ParsedSub::read(path, self.conf.encoding, self.conf.fps)?;
+                // Fails for image based subtitles.
+                sub.text_cues()?;
+                Ok(sub)
+            },
+        )?;
+
+        let mut unchanged = 0;
+        for sub in &mut parsed_subs {
+            let (changed, removed) = self.clean(sub);
+            let name = sub.path.file_name().unwrap_or_default().to_string_lossy();
+            // Subs that are already clean aren't written, so they don't get a backup or a copy
+            // in --out-dir.
+            if changed == 0 && removed == 0 {
+                println!("{}: unchanged", name);
+                unchanged += 1;
+                continue;
+            }
+            println!(
+                "{}: {} cues changed, {} cues removed",
+                name, changed, removed
+            );
+            sub.write()?;
+            report.processed += 1;
+        }
+        if unchanged > 0 {
+            println!("{} subtitle file(s) were already clean", unchanged);
+        }
+        report.finish()
+    }
+
+    /// Cleans the text of all entries and removes the entries that have no text left. Returns
+    /// the number of changed and removed entries.
+    fn clean(&self, sub: &mut ParsedSub) -> (usize, usize) {
+        // SSA uses \N for line breaks inside the entry text.
+        let separator = match sub.format {
+            Format::Subparse(SubtitleFormat::SubStationAlpha) => "\\N",
+            _ => "\n",
+        };
+
+        let mut changed = 0;
+        for entry in &mut sub.entries {
+            let Some(text) = &entry.line else {
+                continue;
+            };
+            let cleaned = text
+                .split(separator)
+                .filter_map(|line| self.clean_line(line))
+                .collect::<Vec<_>>()
+                .join(separator);
+            if cleaned != *text {
+                if !cleaned.split(separator).all(is_empty_line) {
+                    changed += 1;
+                }
+                entry.line = Some(cleaned);
+            }
+        }
+
+        let removed = sub.remove_entries(|_, entry| {
+            entry
+                .line
+                .as_ref()
+                .is_some_and(|text| text.split(separator).all(is_empty_line))
+        });
+        (changed, removed)
+    }
+
+    /// Returns the cleaned line, or `None` if nothing is left of it. Lines that aren't changed
+    /// are returned as is.
+    fn clean_line(&self, line: &str) -> Option<String> {
+        let has_rule = |rule| self.conf.rules.contains(&rule);
+
+        if has_rule(CleanRule::Music) && MUSIC.is_match(line) {
+            return None;
+        }
+
+        let mut cleaned = line.to_string();
+        if has_rule(CleanRule::Sdh) {
+            cleaned = SDH.replace_all(&cleaned, "").to_string();
+        }
+        if has_rule(CleanRule::Speakers) {
+            cleaned = SPEAKER.replace(&cleaned, "$1").to_string();
+        }
+        if has_rule(CleanRule::Tags) {
+            cleaned = TAGS.replace_all(&cleaned, "").to_string();
+        }
+        for pattern in &self.conf.patterns {
+            cleaned = pattern.replace_all(&cleaned, "").to_string();
+        }
+
+        if cleaned == line {
+            Some(cleaned)
+        } else if is_empty_line(&cleaned) {
+            None
+        } else {
+            Some(SPACES.replace_all(cleaned.trim(), " ").to_string())
+        }
+    }
+}
+
+/// Returns true if the line has no text apart from tags, whitespace and dialogue dashes.
+fn is_empty_line(line: &str) -> bool {
+    TAGS.replace_all(line, "")
+        .chars()
+        .all(|c| c.is_whitespace() || c == '-')
+}
@@ -0,0 +1,163 @@
+use crate::commands::util;
+use crate::commands::util::BatchReport;
+use crate::config::{ConvertConfig, ConvertFormat, GlobalConfig};
+use crate::scanner::{self, AreaScan, ScanOptions, SecondaryExtensionPolicy};
+use crate::subtitle::ass::{self, AssEvent, AssStyle};
+use crate::subtitle::{ParsedSub, atomic, srt, vtt};
+use anyhow::Result as AnyResult;
+use encoding_rs::{Encoding, UTF_8};
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::PathBuf;
+use subparse::timetypes::TimeSpan;
+
+pub struct ConvertCommand<'a> {
+    global_conf: &'a GlobalConfig,
+    conf: ConvertConfig,
+}
+
+impl<'a> ConvertCommand<'a> {
+    pub fn new(global_conf: &'a GlobalConfig, conf: ConvertConfig) -> Self {
+        ConvertCommand { global_conf, conf }
+    }
+
+    pub fn run(&self) -> AnyResult<()> {
+        if self.conf.to == ConvertFormat::Vtt
+            && self
+                .conf
+                .output_encoding
+                .is_some_and(|encoding| encoding != UTF_8)
+        {
+            bail!("WebVTT files must be encoded as UTF-8");
+        }
+        if self.conf.replace && self.global_conf.out_dir.is_some() {
+            bail!("--replace can't be used together with --out-dir");
+        }
+
+        let mut report = BatchReport::default();
+        let matches = scanner::scan_subs_only(ScanOptions::from_global_conf(
+            self.global_conf,
+            None,
+            AreaScan::Normal,
+            None,
+            AreaScan::Normal,
+            SecondaryExtensionPolicy::Never,
+        ))?;
+        let matches = util::validate_sub_matches(self.global_conf, matches, &mut report)?;
+
+        let target_ext = self.conf.to.extension();
+        let (already_converted, to_convert): (Vec<PathBuf>, Vec<PathBuf>) = matches
+            .into_iter()
+            .partition(|path| path.extension() == Some(OsStr::new(target_ext)));
+
+        for path in &already_converted {
+            println!("skipping {:?} (already .{})", path, target_ext);
+        }
+
+        // Check all output paths before converting anything so that we never overwrite an
+        // existing file or leave the batch half converted. Subs with the same name in different
+        // formats would be converted to the same file.
+        let mut out_paths = HashSet::new();
+        for path in &to_convert {
+            let out_path = util::output_path(self.global_conf, &path.with_extension(target_ext));
+            if out_path.exists() {
+                bail!("output file {:?} already exists", out_path);
+            }
+            if !out_paths.insert(out_path.clone()) {
+                bail!(
+                    "more than one subtitle would be converted to {:?}, e.g. {:?}",
+                    out_path,
+                    path
+                );
+            }
+        }
+
+        let parsed_subs = util::read_subs(
+            self.global_conf,
+            &to_convert,
+            &mut report,
+            self.output_encoding(),
+            |path| ParsedSub::read(path, self.conf.encoding, self.conf.fps),
+        )?;
+
+        for sub in &parsed_subs {
+            let data = self.convert(sub)?;
+            let out_path =
+                util::output_path(self.global_conf, &sub.path.with_extension(target_ext));
+            atomic::write_file(&out_path, &data, None)?;
+            if self.conf.replace {
+                match &self.global_conf.backup {
+                    Some(suffix) => {
+                        let mut backup_path = sub.path.clone().into_os_string();
+                        backup_path.push(suffix);
+                        fs::rename(&sub.path, backup_path)?;
+                    }
+                    None => fs::remove_file(&sub.path)?,
+                }
+            }
+            report.processed += 1;
+        }
+        report.finish()
+    }
+
+    /// WebVTT is always UTF-8, the other formats keep the encoding of the source file unless
+    /// an output encoding is given.
+    fn output_encoding(&self) -> Option<&'static Encoding> {
+        match self.conf.to {
+            ConvertFormat::Vtt => Some(UTF_8),
+            _ => self.conf.output_encoding,
+        }
+    }
+
+    fn convert(&self, sub: &ParsedSub) -> AnyResult<Vec<u8>> {
+        let cues = sub
+            .text_cues()?
+            .into_iter()
+            .filter_map(|(timespan, text)| Some((timespan, clean_lines(&text)?)))
+            .collect::<Vec<(TimeSpan, String)>>();
+
+        let data = match self.conf.to {
+            ConvertFormat::Srt => {
+                let cues = cues
+                    .into_iter()
+                    .filter_map(|(timespan, text)| {
+                        Some((
+                            timespan,
+                            clean_lines(&srt::strip_tags(&text, &["i", "b", "u"]))?,
+                        ))
+                    })
+                    .collect();
+                srt::write(cues)?
+            }
+            ConvertFormat::Ass => {
+                let style = AssStyle::new("Default", &self.conf.font, self.conf.font_size);
+                let events = cues
+                    .into_iter()
+                    .map(|(timespan, text)| AssEvent {
+                        timespan,
+                        style: &style.name,
+                        text: ass::from_srt_markup(&text),
+                    })
+                    .collect::<Vec<_>>();
+                ass::write(std::slice::from_ref(&style), &events)
+            }
+            ConvertFormat::Vtt => vtt::write(&cues),
+        };
+        sub.encode(&String::from_utf8(data)?)
+    }
+}
+
+/// Removes empty lines and trailing whitespace. Returns `None` if no text is left.
+pub fn clean_lines(text: &str) -> Option<String> {
+    let lines = text
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>();
+    if lines.is_empty() {
+        None
+    } else {
+        Some(lines.join("\n"))
+    }
+}
@@ -0,0 +1,202 @@
+use crate::commands::util;
+use crate::commands::util::BatchReport;
+use crate::config::{DiagnoseConfig, GlobalConfig, StretchConfig};
+use crate::scanner::{self, MatchInfo, ScanOptions};
+use crate::subtitle::ParsedSub;
+use anyhow::Result as AnyResult;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// The frame rates that releases of the same video commonly differ in.
+const FRAME_RATES: &[f64] = &[24000.0 / 1001.0, 24.0, 25.0];
+
+/// The ratio must be this many times closer to a conversion than to 1 for it to be suggested,
+/// since the cues usually span a bit less than the whole video.
+const MIN_EVIDENCE: f64 = 3.0;
+
+pub struct DiagnoseCommand<'a> {
+    global_conf: &'a GlobalConfig,
+    conf: DiagnoseConfig,
+}
+
+/// A sub that looks like it was made for a release with the frame rate `from` while its video has
+/// the frame rate `to`. Stretching its timings by `from / to` fixes it.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Conversion {
+    from: f64,
+    to: f64,
+}
+
+impl Conversion {
+    /// The ratio of the span of the cues to the duration of the video that the conversion causes.
+    fn ratio(&self) -> f64 {
+        self.to / self.from
+    }
+}
+
+impl<'a> DiagnoseCommand<'a> {
+    pub fn new(global_conf: &'a GlobalConfig, conf: DiagnoseConfig) -> Self {
+        DiagnoseCommand { global_conf, conf }
+    }
+
+    pub fn run(&self) -> AnyResult<()> {
+        let mut report = BatchReport::default();
+        let mut matches = scanner::scan(ScanOptions::from_global_and_match_conf(
+            self.global_conf,
+            &self.conf.match_config,
+        ))?;
+        matches.sort_by(|a, b| a.sub_path.cmp(&b.sub_path));
+        let matches = util::validate_sub_and_file_matches(
+            self.global_conf,
+            matches,
+            util::SUPPORTED_SUBTITLE_FORMATS,
+            &mut report,
+        )?;
+
+        let mut flagged: Vec<(PathBuf, Conversion)> = Vec::new();
+        for m in &matches {
+            match self.diagnose(m) {
+                Ok(conversion) => {
+                    flagged.extend(conversion.map(|c| (m.sub_path.clone(), c)));
+                    report.processed += 1;
+                }
+                Err(e) if !self.global_conf.strict => report.skip(&m.sub_path, e),
+                Err(e) => return Err(e.context(format!("failed to diagnose {:?}", m.sub_path))),
+            }
+        }
+
+        // All subs in a batch usually come from the same release, so the most common conversion
+        // is suggested for all of them.
+        let conversions = flagged.iter().map(|(_, c)| *c).collect::<Vec<_>>();
+        let suggestion = conversions
+            .iter()
+            .max_by_key(|c| conversions.iter().filter(|other| other == c).count())
+            .copied();
+        let Some(conversion) = suggestion else {
+            println!(
+                "no frame rate mismatches found in {} subtitle file(s)",
+                report.processed
+            );
+            return report.finish();
+        };
+
+        let paths = flagged
+            .iter()
+            .filter(|(_, c)| *c == conversion)
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        println!(
+            "\n{} of {} subtitle file(s) look like they were made for {} fps instead of {} fps.\n\
+             Their timings can be stretched by {:.5} with --apply.",
+            paths.len(),
+            report.processed,
+            util::format_fps(conversion.from),
+            util::format_fps(conversion.to),
+            conversion.from / conversion.to,
+        );
+
+        if self.conf.apply || (self.global_conf.confirm && ask_apply()?) {
+            self.apply(conversion, &paths, &mut report)?;
+        }
+        report.finish()
+    }
+
+    /// Stretches the subs at `paths`, which were flagged with `conversion`. The other subs are
+    /// left alone, they are either correctly timed or were made for another frame rate.
+    fn apply(
+        &self,
+        conversion: Conversion,
+        paths: &[PathBuf],
+        report: &mut BatchReport,
+    ) -> AnyResult<()> {
+        let stretch = StretchConfig {
+            factor: conversion.from / conversion.to,
+            anchor: 0,
+            fps_conversion: Some((conversion.from, conversion.to)),
+        };
+        let mut parsed_subs = util::read_subs(self.global_conf, paths, report, None, |path| {
+            ParsedSub::read_with_fps(path, self.conf.encoding, conversion.from, conversion.to)
+        })?;
+        for sub in &mut parsed_subs {
+            sub.transform(Some(&stretch), 0);
+            sub.write()?;
+        }
+        println!("stretched {} subtitle file(s)", parsed_subs.len());
+        Ok(())
+    }
+
+    /// Prints the ratio of the span of the cues (from the start of the first cue to the end of the
+    /// last one) to the duration of the video and returns the frame rate conversion that it
+    /// matches, if any.
+    fn diagnose(&self, m: &MatchInfo) -> AnyResult<Option<Conversion>> {
+        let info = m
+            .video_info()
+            .ok_or_else(|| anyhow!("{:?} is not a Matroska or MP4 video", m.video_file_name))?;
+        let duration = info
+            .duration
+            .filter(|&duration| duration > 0)
+            .ok_or_else(|| anyhow!("the duration of {:?} is unknown", m.video_file_name))?;
+        let sub = ParsedSub::read(&m.sub_path, self.conf.encoding, info.fps.unwrap_or(25.0))?;
+        let start = sub
+            .entries
+            .iter()
+            .map(|entry| entry.timespan.start.msecs())
+            .min()
+            .ok_or_else(|| anyhow!("the subtitle has no cues"))?;
+        let end = sub
+            .entries
+            .iter()
+            .map(|entry| entry.timespan.end.msecs())
+            .max()
+            .unwrap_or(start);
+
+        let ratio = (end - start) as f64 / duration as f64;
+        let conversion = self.conversion(ratio, info.fps);
+        println!(
+            "{} -> {}: cues span {}, video is {}, ratio {:.4}: {}",
+            m.sub_file_name,
+            m.video_file_name,
+            util::timestamp(end - start),
+            util::timestamp(duration),
+            ratio,
+            match conversion {
+                Some(c) => format!(
+                    "made for {} fps, stretch by {:.5}",
+                    util::format_fps(c.from),
+                    c.from / c.to
+                ),
+                None => "ok".to_string(),
+            }
+        );
+        Ok(conversion)
+    }
+
+    /// Finds the frame rate conversion whose ratio is closest to `ratio`. Returns `None` if no
+    /// conversion is within the tolerance, which is a fraction of the distance of the conversion's
+    /// ratio to 1, or if the ratio isn't clearly closer to the conversion than to 1. Only
+    /// conversions to the frame rate of the video are considered if it is known.
+    fn conversion(&self, ratio: f64, video_fps: Option<f64>) -> Option<Conversion> {
+        let targets = FRAME_RATES
+            .iter()
+            .filter(|&&to| video_fps.is_none_or(|fps| (fps - to).abs() < 0.01));
+        targets
+            .flat_map(|&to| FRAME_RATES.iter().map(move |&from| Conversion { from, to }))
+            .filter(|c| c.from != c.to)
+            .map(|c| (c, (ratio - c.ratio()).abs()))
+            .filter(|&(c, distance)| {
+                distance <= self.conf.tolerance * (c.ratio() - 1.0).abs()
+                    && distance * MIN_EVIDENCE < (ratio - 1.0).abs()
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(c, _)| c)
+    }
+}
+
+fn ask_apply() -> AnyResult<bool> {
+    print!("Stretch these subs now? (y/N): ");
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    Ok(input.trim().to_lowercase().starts_with('y'))
+}
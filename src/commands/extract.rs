@@ -0,0 +1,315 @@
+use crate::commands::util;
+use crate::commands::util::BatchReport;
+use crate::config::{ExtractConfig, GlobalConfig};
+use crate::mkv::{self, Block, MkvInfo, Track};
+use crate::scanner::{self, AreaScan, ScanOptions, SecondaryExtensionPolicy};
+use crate::subtitle::{ass, atomic, srt};
+use anyhow::Result as AnyResult;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use subparse::timetypes::{TimePoint, TimeSpan};
+
+/// The duration of cues whose block has no duration.
+const DEFAULT_DURATION: i64 = 3000;
+
+const ASS_EVENTS: &str =
+    "[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text";
+
+pub struct ExtractCommand<'a> {
+    global_conf: &'a GlobalConfig,
+    conf: ExtractConfig,
+}
+
+/// The tracks to extract from a video and the path that each track is written to.
+struct ExtractPlan {
+    video: PathBuf,
+    tracks: Vec<(Track, PathBuf)>,
+}
+
+impl<'a> ExtractCommand<'a> {
+    pub fn new(global_conf: &'a GlobalConfig, conf: ExtractConfig) -> Self {
+        ExtractCommand { global_conf, conf }
+    }
+
+    pub fn run(&self) -> AnyResult<()> {
+        let mut report = BatchReport::default();
+        let mut videos = scanner::scan_videos_only(
+            ScanOptions::from_global_conf(
+                self.global_conf,
+                None,
+                AreaScan::Normal,
+                None,
+                AreaScan::Normal,
+                SecondaryExtensionPolicy::Never,
+            ),
+            mkv::EXTENSIONS,
+        )?;
+        if videos.is_empty() {
+            bail!("found no Matroska files");
+        }
+        videos.sort();
+
+        let files = util::read_all(self.global_conf, &videos, &mut report, |path| {
+            Ok((path.to_path_buf(), mkv::read_info(path)?))
+        })?;
+
+        if self.conf.list {
+            for (video, info) in &files {
+                list(video, info);
+                report.processed += 1;
+            }
+            return report.finish();
+        }
+
+        let plans = self.plans(files, &mut report)?;
+
+        // Check all output paths before extracting anything so that we never overwrite an
+        // existing file.
+        for (_, out_path) in plans.iter().flat_map(|plan| &plan.tracks) {
+            if out_path.exists() {
+                bail!("output file {:?} already exists", out_path);
+            }
+        }
+
+        for plan in &plans {
+            let numbers = plan
+                .tracks
+                .iter()
+                .map(|(track, _)| track.number)
+                .collect::<Vec<_>>();
+            let blocks = match mkv::read_blocks(&plan.video, &numbers) {
+                Ok((_, blocks)) => blocks,
+                Err(e) if !self.global_conf.strict => {
+                    report.skip(&plan.video, e);
+                    continue;
+                }
+                Err(e) => return Err(e.context(format!("failed to read {:?}", plan.video))),
+            };
+
+            for (track, out_path) in &plan.tracks {
+                let blocks = blocks
+                    .iter()
+                    .filter(|block| block.track == track.number)
+                    .collect::<Vec<_>>();
+                let data = match format(track) {
+                    Some("srt") => srt_data(&blocks)?,
+                    _ => ass_data(track, &blocks),
+                };
+                atomic::write_file(out_path, &data, None)?;
+                println!(
+                    "{}: track {} -> {}",
+                    file_name(&plan.video),
+                    track.number,
+                    file_name(out_path)
+                );
+            }
+            report.processed += 1;
+        }
+        report.finish()
+    }
+
+    /// Chooses the tracks to extract from each video and their output paths. Videos without
+    /// any chosen tracks are skipped.
+    fn plans(
+        &self,
+        files: Vec<(PathBuf, MkvInfo)>,
+        report: &mut BatchReport,
+    ) -> AnyResult<Vec<ExtractPlan>> {
+        let mut plans = Vec::new();
+        for (video, info) in files {
+            let mut tracks = Vec::new();
+            for track in info.tracks.into_iter().filter(Track::is_subtitle) {
+                if !self.conf.tracks.is_empty() && !self.conf.tracks.contains(&track.number) {
+                    continue;
+                }
+                if !self.conf.languages.is_empty()
+                    && !self
+                        .conf
+                        .languages
+                        .iter()
+                        .any(|lang| lang.eq_ignore_ascii_case(track.language()))
+                {
+                    continue;
+                }
+                if format(&track).is_none() {
+                    // Tracks that were chosen by number must be extracted.
+                    if self.conf.tracks.contains(&track.number) {
+                        bail!(
+                            "{:?}: track {} has codec {} which can't be extracted",
+                            video,
+                            track.number,
+                            track.codec
+                        );
+                    }
+                    continue;
+                }
+                tracks.push(track);
+            }
+
+            if tracks.is_empty() {
+                let reason = "has no matching SRT or ASS subtitle tracks";
+                if self.global_conf.strict {
+                    bail!("{:?}: {}", video, reason);
+                }
+                report.skip(&video, reason);
+                continue;
+            }
+
+            // Tracks with the same language and format get the track number in their name.
+            let mut counts = HashMap::new();
+            for track in &tracks {
+                *counts.entry((track.language(), format(track))).or_insert(0) += 1;
+            }
+            let video_stem = video.file_stem().unwrap_or_default().to_string_lossy();
+            let names = tracks
+                .iter()
+                .map(|track| {
+                    let ext = format(track).unwrap_or_default();
+                    if counts[&(track.language(), format(track))] > 1 {
+                        format!(
+                            "{}.{}-{}.{}",
+                            video_stem,
+                            track.language(),
+                            track.number,
+                            ext
+                        )
+                    } else {
+                        format!("{}.{}.{}", video_stem, track.language(), ext)
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            let tracks = tracks
+                .into_iter()
+                .zip(names)
+                .map(|(track, name)| {
+                    let out_path = util::output_path(self.global_conf, &video.with_file_name(name));
+                    (track, out_path)
+                })
+                .collect();
+            plans.push(ExtractPlan { video, tracks });
+        }
+
+        if plans.is_empty() {
+            bail!("found no matching SRT or ASS subtitle tracks");
+        }
+        Ok(plans)
+    }
+}
+
+fn list(video: &Path, info: &MkvInfo) {
+    let tracks = info
+        .tracks
+        .iter()
+        .filter(|track| track.is_subtitle())
+        .collect::<Vec<_>>();
+    if tracks.is_empty() {
+        println!("{}: no subtitle tracks", file_name(video));
+    }
+    for track in tracks {
+        let mut line = format!(
+            "{}: track {}: {}, {}",
+            file_name(video),
+            track.number,
+            track.language(),
+            track.codec
+        );
+        match format(track) {
+            Some(ext) => line += &format!(" ({})", ext),
+            None => line += " (can't be extracted)",
+        }
+        if let Some(name) = &track.name {
+            line += &format!(", {:?}", name);
+        }
+        if track.default {
+            line += ", default";
+        }
+        if track.forced {
+            line += ", forced";
+        }
+        println!("{}", line);
+    }
+}
+
+/// Returns the file extension of the subtitles of the track, or `None` if the track can't be
+/// extracted.
+fn format(track: &Track) -> Option<&'static str> {
+    match track.codec.as_str() {
+        "S_TEXT/UTF8" => Some("srt"),
+        "S_TEXT/ASS" | "S_ASS" => Some("ass"),
+        "S_TEXT/SSA" | "S_SSA" => Some("ssa"),
+        _ => None,
+    }
+}
+
+/// The timespan of the cue in a block. Blocks without a duration get `DEFAULT_DURATION`.
+pub fn timespan(block: &Block) -> TimeSpan {
+    TimeSpan::new(
+        TimePoint::from_msecs(block.start),
+        TimePoint::from_msecs(block.start + block.duration.unwrap_or(DEFAULT_DURATION)),
+    )
+}
+
+fn text(block: &Block) -> String {
+    String::from_utf8_lossy(&block.data)
+        .replace("\r\n", "\n")
+        .trim_end()
+        .to_string()
+}
+
+fn srt_data(blocks: &[&Block]) -> AnyResult<Vec<u8>> {
+    let mut cues = blocks
+        .iter()
+        .map(|block| (timespan(block), text(block)))
+        .collect::<Vec<_>>();
+    cues.sort_by_key(|(timespan, _)| timespan.start);
+    srt::write(cues)
+}
+
+/// The blocks of SSA and ASS tracks have the fields of a Dialogue line without the start and end
+/// time, after a read order field: `ReadOrder, Layer, Style, Name, MarginL, MarginR, MarginV,
+/// Effect, Text`. The header of the file is the codec private data of the track.
+fn ass_data(track: &Track, blocks: &[&Block]) -> Vec<u8> {
+    let mut events = blocks
+        .iter()
+        .filter_map(|block| {
+            let text = text(block);
+            let mut fields = text.splitn(3, ',');
+            let read_order = fields.next()?.trim().parse::<i64>().ok()?;
+            let layer = fields.next()?;
+            let rest = fields.next()?;
+            let span = timespan(block);
+            Some((
+                read_order,
+                format!(
+                    "Dialogue: {},{},{},{}",
+                    layer,
+                    ass::timestamp(span.start),
+                    ass::timestamp(span.end),
+                    rest
+                ),
+            ))
+        })
+        .collect::<Vec<_>>();
+    events.sort_by_key(|(read_order, _)| *read_order);
+
+    let header = String::from_utf8_lossy(&track.codec_private).replace("\r\n", "\n");
+    let mut out = header.trim_end_matches(['\n', '\0']).to_string();
+    if !out.contains("[Events]") {
+        out += "\n\n";
+        out += ASS_EVENTS;
+    }
+    for (_, event) in events {
+        out += "\n";
+        out += &event;
+    }
+    out += "\n";
+    out.into_bytes()
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
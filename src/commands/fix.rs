@@ -0,0 +1,221 @@
+use crate::commands::util;
+use crate::commands::util::BatchReport;
+use crate::config::{FixConfig, GlobalConfig};
+use crate::scanner::{self, AreaScan, ScanOptions, SecondaryExtensionPolicy};
+use crate::subtitle::ParsedSub;
+use anyhow::Result as AnyResult;
+use subparse::timetypes::{TimeDelta, TimePoint};
+
+pub struct FixCommand<'a> {
+    global_conf: &'a GlobalConfig,
+    conf: FixConfig,
+}
+
+/// A change to a cue. `index` is the 1-based index of the cue in the file.
+struct Change {
+    index: usize,
+    time: TimePoint,
+    message: String,
+}
+
+impl<'a> FixCommand<'a> {
+    pub fn new(global_conf: &'a GlobalConfig, conf: FixConfig) -> Self {
+        FixCommand { global_conf, conf }
+    }
+
+    pub fn run(&self) -> AnyResult<()> {
+        let mut report = BatchReport::default();
+        let mut matches = scanner::scan_subs_only(ScanOptions::from_global_conf(
+            self.global_conf,
+            None,
+            AreaScan::Normal,
+            None,
+            AreaScan::Normal,
+            SecondaryExtensionPolicy::Never,
+        ))?;
+        matches.sort();
+        let matches = util::validate_sub_matches(self.global_conf, matches, &mut report)?;
+
+        let mut parsed_subs = util::read_subs(
+            self.global_conf,
+            &matches,
+            &mut report,
+            self.conf.output_encoding,
+            |path| {
+                let sub = ParsedSub::read(path, self.conf.encoding, self.conf.fps)?;
+                // Fails for image based subtitles, whose cues can't be reordered or merged.
+                sub.text_cues()?;
+                Ok(sub)
+            },
+        )?;
+
+        let mut change_count = 0;
+        let mut changed_files = 0;
+        for sub in &mut parsed_subs {
+            let changes = self.fix(sub);
+            for change in &changes {
+                println!(
+                    "{}:{}: {}: {}",
+                    sub.path.file_name().unwrap_or_default().to_string_lossy(),
+                    change.index,
+                    util::timestamp(change.time.msecs()),
+                    change.message
+                );
+            }
+            if !changes.is_empty() {
+                change_count += changes.len();
+                changed_files += 1;
+                if !self.conf.dry_run {
+                    sub.write()?;
+                }
+            }
+            report.processed += 1;
+        }
+
+        println!(
+            "{} change(s) in {} of {} subtitle file(s){}",
+            change_count,
+            changed_files,
+            parsed_subs.len(),
+            if self.conf.dry_run {
+                " (dry run, nothing was written)"
+            } else {
+                ""
+            }
+        );
+        report.finish()
+    }
+
+    /// Fixes the timings of all entries. Returns the changes in the order they were made.
+    fn fix(&self, sub: &mut ParsedSub) -> Vec<Change> {
+        let mut changes = Vec::new();
+        let mut change = |index: usize, time, message: String| {
+            changes.push(Change {
+                index: index + 1,
+                time,
+                message,
+            })
+        };
+
+        if sub.sort_entries() {
+            for (i, entry) in sub.entries.iter().enumerate() {
+                if sub.file_index(i) != i {
+                    change(
+                        sub.file_index(i),
+                        entry.timespan.start,
+                        format!("moved to position {}", i + 1),
+                    );
+                }
+            }
+        }
+
+        for i in 0..sub.entries.len() {
+            let index = sub.file_index(i);
+            let span = &mut sub.entries[i].timespan;
+            if span.start.msecs() < 0 {
+                change(
+                    index,
+                    span.start,
+                    format!(
+                        "start {} -> {}",
+                        util::timestamp(span.start.msecs()),
+                        util::timestamp(0)
+                    ),
+                );
+                span.start = TimePoint::from_msecs(0);
+                span.end = span.end.max(span.start);
+            }
+        }
+
+        // Cues with the same text that overlap or are less than the minimum gap apart are
+        // merged into the first one.
+        let gap = TimeDelta::from_msecs(self.conf.min_gap);
+        let mut duplicates = Vec::with_capacity(sub.entries.len());
+        let mut previous: Option<usize> = None;
+        for i in 0..sub.entries.len() {
+            if let Some(p) = previous
+                && sub.entries[i].line.is_some()
+                && sub.entries[i].line == sub.entries[p].line
+                && sub.entries[i].timespan.start <= sub.entries[p].timespan.end + gap
+            {
+                change(
+                    sub.file_index(i),
+                    sub.entries[i].timespan.start,
+                    format!("merged into cue {}", sub.file_index(p) + 1),
+                );
+                let end = sub.entries[i].timespan.end;
+                let span = &mut sub.entries[p].timespan;
+                span.end = span.end.max(end);
+                duplicates.push(true);
+                continue;
+            }
+            duplicates.push(false);
+            previous = Some(i);
+        }
+        sub.remove_entries(|i, _| duplicates[i]);
+
+        for i in 0..sub.entries.len() {
+            let span = sub.entries[i].timespan;
+            let duration = (span.end - span.start).msecs();
+            let mut end = span.end;
+            let mut reason = String::new();
+
+            if let Some(max) = self.conf.max_duration
+                && duration > max
+            {
+                end = span.start + TimeDelta::from_msecs(max);
+                reason = format!("longer than {}ms", max);
+            }
+            if let Some(min) = self.conf.min_duration
+                && duration < min
+            {
+                end = span.start + TimeDelta::from_msecs(min);
+                reason = format!("shorter than {}ms", min);
+            }
+
+            // Cues that start at the same time are shown together, so the next cue is the first
+            // one that starts later.
+            if let Some(j) =
+                (i + 1..sub.entries.len()).find(|&j| sub.entries[j].timespan.start > span.start)
+            {
+                let next_start = sub.entries[j].timespan.start;
+                // If the gap doesn't fit the cue only ends when the next one starts.
+                let allowed = if next_start - gap > span.start {
+                    next_start - gap
+                } else {
+                    next_start
+                };
+                if end > allowed {
+                    // Otherwise the cue is still extended to the minimum duration, just less.
+                    if allowed < span.end {
+                        reason = if span.end > next_start {
+                            format!("overlaps cue {}", sub.file_index(j) + 1)
+                        } else {
+                            format!(
+                                "less than {}ms before cue {}",
+                                self.conf.min_gap,
+                                sub.file_index(j) + 1
+                            )
+                        };
+                    }
+                    end = allowed;
+                }
+            }
+
+            if end != span.end {
+                change(
+                    sub.file_index(i),
+                    span.start,
+                    format!(
+                        "end {} -> {} ({})",
+                        util::timestamp(span.end.msecs()),
+                        util::timestamp(end.msecs()),
+                        reason
+                    ),
+                );
+                sub.entries[i].timespan.end = end;
+            }
+        }
+        changes
+    }
+}
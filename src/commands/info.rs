@@ -0,0 +1,195 @@
+use crate::commands::util;
+use crate::commands::util::{BatchReport, PartialSuccess};
+use crate::config::{GlobalConfig, InfoConfig};
+use crate::scanner::{self, AreaScan, ScanOptions, SecondaryExtensionPolicy};
+use crate::subtitle::ParsedSub;
+use anyhow::Result as AnyResult;
+use std::fmt::Write;
+
+pub struct InfoCommand<'a> {
+    global_conf: &'a GlobalConfig,
+    conf: InfoConfig,
+}
+
+/// The info about one subtitle file. Times are in milliseconds.
+struct SubInfo {
+    file: String,
+    format: &'static str,
+    encoding: &'static str,
+    cues: usize,
+    first: Option<i64>,
+    last: Option<i64>,
+    overlapping: usize,
+    secondary_ext: Option<String>,
+}
+
+impl SubInfo {
+    fn new(sub: &ParsedSub) -> Self {
+        let mut timespans = sub
+            .entries
+            .iter()
+            .map(|entry| entry.timespan)
+            .collect::<Vec<_>>();
+        timespans.sort_by_key(|timespan| timespan.start);
+
+        // Cues that start before an earlier cue has ended.
+        let mut overlapping = 0;
+        let mut latest_end = None;
+        for timespan in &timespans {
+            if latest_end.is_some_and(|end| timespan.start < end) {
+                overlapping += 1;
+            }
+            latest_end = latest_end.max(Some(timespan.end));
+        }
+
+        SubInfo {
+            file: sub
+                .path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            format: sub.format.name(),
+            encoding: sub.encoding.name(),
+            cues: timespans.len(),
+            first: timespans.first().map(|timespan| timespan.start.msecs()),
+            last: latest_end.map(|end| end.msecs()),
+            overlapping,
+            secondary_ext: scanner::secondary_extension(&sub.path, SecondaryExtensionPolicy::Maybe),
+        }
+    }
+
+    /// The time from the start of the first cue to the end of the last cue.
+    fn span(&self) -> Option<i64> {
+        Some(self.last? - self.first?)
+    }
+}
+
+impl<'a> InfoCommand<'a> {
+    pub fn new(global_conf: &'a GlobalConfig, conf: InfoConfig) -> Self {
+        InfoCommand { global_conf, conf }
+    }
+
+    pub fn run(&self) -> AnyResult<()> {
+        let mut report = BatchReport::default();
+        let mut matches = scanner::scan_subs_only(ScanOptions::from_global_conf(
+            self.global_conf,
+            None,
+            AreaScan::Normal,
+            None,
+            AreaScan::Normal,
+            SecondaryExtensionPolicy::Never,
+        ))?;
+        matches.sort();
+        let matches = util::validate_sub_matches(self.global_conf, matches, &mut report)?;
+
+        let infos = util::read_all(self.global_conf, &matches, &mut report, |path| {
+            Ok(SubInfo::new(&ParsedSub::read(
+                path,
+                self.conf.encoding,
+                self.conf.fps,
+            )?))
+        })?;
+        report.processed = infos.len();
+
+        if self.conf.json {
+            println!("{}", json(&infos, &report));
+            // The skipped files are part of the JSON output.
+            if !report.skipped.is_empty() {
+                return Err(PartialSuccess {
+                    processed: report.processed,
+                    skipped: report.skipped.len(),
+                    failed: 0,
+                }
+                .into());
+            }
+            return Ok(());
+        }
+        print!("{}", table(&infos));
+        report.finish()
+    }
+}
+
+fn table(infos: &[SubInfo]) -> String {
+    let header = [
+        "FILE", "FORMAT", "ENCODING", "CUES", "FIRST", "LAST", "SPAN", "OVERLAPS", "SEC.EXT",
+    ];
+    let rows = infos
+        .iter()
+        .map(|info| {
+            vec![
+                info.file.clone(),
+                info.format.to_string(),
+                info.encoding.to_string(),
+                info.cues.to_string(),
+                info.first.map(util::timestamp).unwrap_or_default(),
+                info.last.map(util::timestamp).unwrap_or_default(),
+                info.span().map(util::timestamp).unwrap_or_default(),
+                info.overlapping.to_string(),
+                info.secondary_ext.clone().unwrap_or_default(),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    util::table(&header, &rows)
+}
+
+fn json(infos: &[SubInfo], report: &BatchReport) -> String {
+    let number = |n: Option<i64>| n.map(|n| n.to_string()).unwrap_or("null".to_string());
+    let subs = infos
+        .iter()
+        .map(|info| {
+            format!(
+                "{{\"file\":{},\"format\":{},\"encoding\":{},\"cues\":{},\"first_ms\":{},\
+                \"last_ms\":{},\"span_ms\":{},\"overlapping\":{},\"secondary_ext\":{}}}",
+                json_string(&info.file),
+                json_string(info.format),
+                json_string(info.encoding),
+                info.cues,
+                number(info.first),
+                number(info.last),
+                number(info.span()),
+                info.overlapping,
+                info.secondary_ext
+                    .as_deref()
+                    .map(json_string)
+                    .unwrap_or("null".to_string())
+            )
+        })
+        .collect::<Vec<_>>();
+    let skipped = report
+        .skipped
+        .iter()
+        .map(|skipped| {
+            format!(
+                "{{\"file\":{},\"reason\":{}}}",
+                json_string(&skipped.path.to_string_lossy()),
+                json_string(&skipped.reason)
+            )
+        })
+        .collect::<Vec<_>>();
+    format!(
+        "{{\"subtitles\":[{}],\"skipped\":[{}]}}",
+        subs.join(","),
+        skipped.join(",")
+    )
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,191 @@
+use crate::commands::util;
+use crate::commands::util::BatchReport;
+use crate::config::{GlobalConfig, LintConfig};
+use crate::scanner::{self, AreaScan, ScanOptions, SecondaryExtensionPolicy};
+use crate::subtitle::{ParsedSub, srt};
+use anyhow::Result as AnyResult;
+use std::collections::HashMap;
+use subparse::timetypes::TimePoint;
+
+pub struct LintCommand<'a> {
+    global_conf: &'a GlobalConfig,
+    conf: LintConfig,
+}
+
+/// A problem with a cue. `index` is the 1-based index of the cue in the file.
+struct Problem {
+    index: usize,
+    time: TimePoint,
+    message: String,
+}
+
+impl<'a> LintCommand<'a> {
+    pub fn new(global_conf: &'a GlobalConfig, conf: LintConfig) -> Self {
+        LintCommand { global_conf, conf }
+    }
+
+    pub fn run(&self) -> AnyResult<()> {
+        let mut report = BatchReport::default();
+        let mut matches = scanner::scan_subs_only(ScanOptions::from_global_conf(
+            self.global_conf,
+            None,
+            AreaScan::Normal,
+            None,
+            AreaScan::Normal,
+            SecondaryExtensionPolicy::Never,
+        ))?;
+        matches.sort();
+        let matches = util::validate_sub_matches(self.global_conf, matches, &mut report)?;
+
+        let parsed_subs = util::read_subs(self.global_conf, &matches, &mut report, None, |path| {
+            ParsedSub::read(path, self.conf.encoding, self.conf.fps)
+        })?;
+
+        // Each sub is checked against the length of its own video unless --video-length is given.
+        let video_lengths = match self.conf.video_length {
+            Some(_) => HashMap::new(),
+            None => util::video_durations(self.global_conf)?,
+        };
+
+        let mut problem_count = 0;
+        let mut files_with_problems = 0;
+        for sub in &parsed_subs {
+            let video_length = self
+                .conf
+                .video_length
+                .or_else(|| video_lengths.get(&sub.path).copied());
+            let problems = self.lint(sub, video_length);
+            for problem in &problems {
+                println!(
+                    "{}:{}: {}: {}",
+                    sub.path.file_name().unwrap_or_default().to_string_lossy(),
+                    problem.index,
+                    util::timestamp(problem.time.msecs()),
+                    problem.message
+                );
+            }
+            if !problems.is_empty() {
+                problem_count += problems.len();
+                files_with_problems += 1;
+            }
+            report.processed += 1;
+        }
+
+        if problem_count > 0 {
+            report.finish().ok();
+            bail!(
+                "found {} problem(s) in {} of {} subtitle file(s)",
+                problem_count,
+                files_with_problems,
+                parsed_subs.len()
+            );
+        }
+        report.finish()
+    }
+
+    fn lint(&self, sub: &ParsedSub, video_length: Option<i64>) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        let mut problem = |index: usize, time, message: String| {
+            problems.push(Problem {
+                index: index + 1,
+                time,
+                message,
+            })
+        };
+
+        // Image based subs have no text so only their timings are checked.
+        let texts = sub.text_cues().ok();
+
+        let mut previous_start = None;
+        let mut latest_end: Option<(usize, TimePoint)> = None;
+        for (i, entry) in sub.entries.iter().enumerate() {
+            let span = entry.timespan;
+
+            if previous_start.is_some_and(|start| span.start < start) {
+                problem(i, span.start, "starts before the previous cue".to_string());
+            }
+            previous_start = Some(span.start);
+
+            if span.start.msecs() < 0 {
+                problem(i, span.start, "starts before 00:00:00".to_string());
+            }
+            if span.end <= span.start {
+                problem(
+                    i,
+                    span.start,
+                    format!("has a duration of {}ms", (span.end - span.start).msecs()),
+                );
+            }
+
+            if let Some((j, end)) = latest_end
+                && span.start < end
+                && span.start >= sub.entries[j].timespan.start
+            {
+                problem(
+                    i,
+                    span.start,
+                    format!(
+                        "overlaps cue {} by {}ms",
+                        j + 1,
+                        (end.min(span.end) - span.start).msecs()
+                    ),
+                );
+            }
+            if latest_end.is_none_or(|(_, end)| span.end > end) {
+                latest_end = Some((i, span.end));
+            }
+
+            if let Some(length) = video_length
+                && span.end.msecs() > length
+            {
+                problem(
+                    i,
+                    span.start,
+                    format!(
+                        "ends {}ms after the end of the video",
+                        span.end.msecs() - length
+                    ),
+                );
+            }
+
+            let Some((_, text)) = texts.as_ref().map(|texts| &texts[i]) else {
+                continue;
+            };
+            let text = srt::strip_tags(text, &[]);
+            let duration = (span.end - span.start).msecs();
+            let chars = text
+                .lines()
+                .map(|line| line.trim().chars().count())
+                .sum::<usize>();
+            if duration > 0 {
+                let cps = chars as f64 / (duration as f64 / 1000.0);
+                if cps > self.conf.max_cps {
+                    problem(
+                        i,
+                        span.start,
+                        format!(
+                            "has {:.1} characters per second (max {})",
+                            cps, self.conf.max_cps
+                        ),
+                    );
+                }
+            }
+            for (n, line) in text.lines().enumerate() {
+                let length = line.trim().chars().count();
+                if length > self.conf.max_line_length {
+                    problem(
+                        i,
+                        span.start,
+                        format!(
+                            "line {} has {} characters (max {})",
+                            n + 1,
+                            length,
+                            self.conf.max_line_length
+                        ),
+                    );
+                }
+            }
+        }
+        problems
+    }
+}
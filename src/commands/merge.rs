@@ -0,0 +1,259 @@
+use crate::commands::convert::clean_lines;
+use crate::commands::util;
+use crate::commands::util::BatchReport;
+use crate::config::{GlobalConfig, MergeConfig, MergeFormat};
+use crate::scanner::{self, MatchInfo, ScanOptions};
+use crate::subtitle::ass::{self, AssEvent, AssStyle};
+use crate::subtitle::{ParsedSub, atomic, encoding, srt};
+use anyhow::Result as AnyResult;
+use encoding_rs::UTF_8;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use subparse::timetypes::TimeSpan;
+
+type Cue = (TimeSpan, String);
+
+pub struct MergeCommand<'a> {
+    global_conf: &'a GlobalConfig,
+    conf: MergeConfig,
+}
+
+/// The top and bottom subs of a video and the path of the merged sub.
+struct MergePlan {
+    top: PathBuf,
+    bottom: PathBuf,
+    out_path: PathBuf,
+}
+
+impl<'a> MergeCommand<'a> {
+    pub fn new(global_conf: &'a GlobalConfig, conf: MergeConfig) -> Self {
+        MergeCommand { global_conf, conf }
+    }
+
+    pub fn run(&self) -> AnyResult<()> {
+        let mut report = BatchReport::default();
+        let matches = scanner::scan(ScanOptions::from_global_and_match_conf(
+            self.global_conf,
+            &self.conf.match_config,
+        ))?;
+        let plans = self.plans(matches, &mut report)?;
+
+        // Check all output paths before merging anything so that we never overwrite an
+        // existing file.
+        for plan in &plans {
+            if plan.out_path.exists() {
+                bail!("output file {:?} already exists", plan.out_path);
+            }
+        }
+
+        let paths = plans
+            .iter()
+            .flat_map(|plan| [plan.top.clone(), plan.bottom.clone()])
+            .collect::<Vec<_>>();
+        let subs = util::read_subs(self.global_conf, &paths, &mut report, None, |path| {
+            ParsedSub::read(path, self.conf.encoding, self.conf.fps)
+        })?
+        .into_iter()
+        .map(|sub| (sub.path.clone(), sub))
+        .collect::<HashMap<_, _>>();
+
+        for plan in &plans {
+            // Subs that couldn't be read are already reported as skipped.
+            let (Some(top), Some(bottom)) = (subs.get(&plan.top), subs.get(&plan.bottom)) else {
+                continue;
+            };
+            let data = self.merge(top.text_cues()?, bottom.text_cues()?)?;
+            let encoding = self.conf.output_encoding.unwrap_or(UTF_8);
+            atomic::write_file(
+                &plan.out_path,
+                &encoding::encode_file(&data, encoding, false)?,
+                None,
+            )?;
+            println!(
+                "{} + {} -> {}",
+                file_name(&plan.top),
+                file_name(&plan.bottom),
+                file_name(&plan.out_path)
+            );
+            report.processed += 1;
+        }
+        report.finish()
+    }
+
+    /// Finds the top and bottom sub of each matched video. Videos that only have one of them
+    /// are skipped.
+    fn plans(
+        &self,
+        matches: Vec<MatchInfo>,
+        report: &mut BatchReport,
+    ) -> AnyResult<Vec<MergePlan>> {
+        let mut videos: BTreeMap<PathBuf, Vec<MatchInfo>> = BTreeMap::new();
+        for m in matches {
+            videos.entry(m.video_path.clone()).or_default().push(m);
+        }
+
+        let mut plans = Vec::new();
+        for (video, subs) in videos {
+            let find = |lang: &str| {
+                subs.iter()
+                    .find(|m| secondary_ext(m) == Some(lang))
+                    .map(|m| m.sub_path.clone())
+            };
+            let (top, bottom) = match (find(&self.conf.top), find(&self.conf.bottom)) {
+                (Some(top), Some(bottom)) => (top, bottom),
+                (top, _) => {
+                    let missing = if top.is_none() {
+                        &self.conf.top
+                    } else {
+                        &self.conf.bottom
+                    };
+                    let reason = format!("found no .{} subtitle", missing);
+                    if self.global_conf.strict {
+                        bail!("{:?}: {}", video, reason);
+                    }
+                    report.skip(&video, reason);
+                    continue;
+                }
+            };
+
+            let ext = match self.conf.format {
+                MergeFormat::Ass => "ass",
+                MergeFormat::Srt => "srt",
+            };
+            let video_stem = video.file_stem().unwrap_or_default().to_string_lossy();
+            let name = self
+                .conf
+                .name
+                .replace("{video}", &video_stem)
+                .replace("{top}", &self.conf.top)
+                .replace("{bottom}", &self.conf.bottom)
+                .replace("{ext}", ext);
+            plans.push(MergePlan {
+                top,
+                bottom,
+                out_path: util::output_path(self.global_conf, &video.with_file_name(name)),
+            });
+        }
+
+        if plans.is_empty() {
+            bail!(
+                "found no videos with both a .{} and a .{} subtitle",
+                self.conf.top,
+                self.conf.bottom
+            );
+        }
+        Ok(plans)
+    }
+
+    fn merge(&self, top: Vec<Cue>, bottom: Vec<Cue>) -> AnyResult<String> {
+        let pairs = pair_cues(&top, &bottom, self.conf.tolerance);
+
+        let data = match self.conf.format {
+            MergeFormat::Srt => {
+                let mut cues = top
+                    .iter()
+                    .zip(&pairs)
+                    .map(|((timespan, text), pair)| match pair {
+                        Some(j) => {
+                            let (bottom_span, bottom_text) = &bottom[*j];
+                            (
+                                TimeSpan::new(
+                                    timespan.start.min(bottom_span.start),
+                                    timespan.end.max(bottom_span.end),
+                                ),
+                                format!("{}\n{}", text, bottom_text),
+                            )
+                        }
+                        None => (*timespan, text.clone()),
+                    })
+                    .chain(
+                        bottom
+                            .iter()
+                            .enumerate()
+                            .filter(|(j, _)| !pairs.contains(&Some(*j)))
+                            .map(|(_, cue)| cue.clone()),
+                    )
+                    .filter_map(|(timespan, text)| {
+                        Some((
+                            timespan,
+                            clean_lines(&srt::strip_tags(&text, &["i", "b", "u"]))?,
+                        ))
+                    })
+                    .collect::<Vec<_>>();
+                cues.sort_by_key(|(timespan, _)| timespan.start);
+                srt::write(cues)?
+            }
+            MergeFormat::Ass => {
+                let mut top_style = AssStyle::new("Top", &self.conf.font, self.conf.font_size);
+                top_style.alignment = 8;
+                top_style.color = self.conf.top_color;
+                let mut bottom_style =
+                    AssStyle::new("Bottom", &self.conf.font, self.conf.font_size);
+                bottom_style.color = self.conf.bottom_color;
+
+                // Joined bottom cues get the timing of their top cue.
+                let mut bottom_spans = bottom.iter().map(|(span, _)| *span).collect::<Vec<_>>();
+                for (i, pair) in pairs.iter().enumerate() {
+                    if let Some(j) = pair {
+                        bottom_spans[*j] = top[i].0;
+                    }
+                }
+
+                let mut events = top
+                    .iter()
+                    .map(|(timespan, text)| (*timespan, &top_style, text))
+                    .chain(
+                        bottom_spans
+                            .into_iter()
+                            .zip(&bottom)
+                            .map(|(timespan, (_, text))| (timespan, &bottom_style, text)),
+                    )
+                    .filter_map(|(timespan, style, text)| {
+                        Some(AssEvent {
+                            timespan,
+                            style: &style.name,
+                            text: ass::from_srt_markup(&clean_lines(text)?),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                events.sort_by_key(|event| event.timespan.start);
+                ass::write(&[top_style.clone(), bottom_style.clone()], &events)
+            }
+        };
+        Ok(String::from_utf8(data)?)
+    }
+}
+
+/// Pairs each top cue with the bottom cue that starts closest to it, if that is within
+/// `tolerance` milliseconds and the bottom cue isn't paired already. Returns the index of the
+/// paired bottom cue for each top cue.
+fn pair_cues(top: &[Cue], bottom: &[Cue], tolerance: i64) -> Vec<Option<usize>> {
+    let mut paired = vec![false; bottom.len()];
+    top.iter()
+        .map(|(top_span, _)| {
+            let (j, _) = bottom
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| !paired[*j])
+                .map(|(j, (span, _))| (j, (span.start - top_span.start).msecs().abs()))
+                .filter(|(_, diff)| *diff <= tolerance)
+                .min_by_key(|(_, diff)| *diff)?;
+            paired[j] = true;
+            Some(j)
+        })
+        .collect()
+}
+
+/// Returns the secondary extension (e.g. en for ep01.en.srt) of the matched sub.
+fn secondary_ext(m: &MatchInfo) -> Option<&str> {
+    let ext = m.sub_file_ext.to_str()?;
+    let (secondary, _) = ext.split_once('.')?;
+    Some(secondary)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
@@ -1,5 +1,6 @@
 use crate::commands::time::TimeCommand;
 use crate::commands::util;
+use crate::commands::util::BatchReport;
 use crate::config::{GlobalConfig, TimeConfig};
 use crate::scanner;
 use crate::scanner::{AreaScan, MatchInfo, ScanOptions, SecondaryExtensionPolicy};
@@ -33,6 +34,10 @@ impl<'a> MpvCommand<'a> {
     }
 
     pub fn run(&self) -> AnyResult<()> {
+        // mpv reloads the subtitle it has loaded, so the subtitle has to be changed in place.
+        if self.global_conf.out_dir.is_some() {
+            bail!("--out-dir can't be used with time-mpv");
+        }
         let mpv = which::which("mpv").context("could not find `mpv` in PATH. Is mpv installed?")?;
         let target = self.first_sub_video_match()?;
         let socket = MpvSocket::create_socket()?;
@@ -55,7 +60,7 @@ impl<'a> MpvCommand<'a> {
     }
 
     fn first_sub_video_match(&self) -> AnyResult<MatchInfo> {
-        let mut matches = scanner::scan(ScanOptions::from_global_conf(
+        let matches = scanner::scan(ScanOptions::from_global_conf(
             self.global_conf,
             None,
             AreaScan::Normal,
@@ -63,7 +68,12 @@ impl<'a> MpvCommand<'a> {
             AreaScan::Normal,
             SecondaryExtensionPolicy::Never,
         ))?;
-        util::validate_sub_and_file_matches(self.global_conf, &matches)?;
+        let mut matches = util::validate_sub_and_file_matches(
+            self.global_conf,
+            matches,
+            util::SUPPORTED_SUBTITLE_FORMATS,
+            &mut BatchReport::default(),
+        )?;
         Ok(matches.swap_remove(0))
     }
 
@@ -145,7 +155,8 @@ impl<'a> MpvCommand<'a> {
     }
 
     fn shift_subs(&self, conn: &mut MpvConnection, timing: i64) -> AnyResult<i64> {
-        TimeCommand::new(self.global_conf, TimeConfig::timing(timing)).run()?;
+        // Subs that can't be adjusted are skipped silently since we are in raw terminal mode.
+        TimeCommand::new(self.global_conf, TimeConfig::timing(timing)).run_batch()?;
         let resp = conn.send_wait(r#"{ "command": ["sub_reload"] }"#)?;
         if !resp.contains("success") {
             bail!(resp);
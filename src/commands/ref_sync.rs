@@ -0,0 +1,178 @@
+use crate::align::{self, AlignOptions, Cue};
+use crate::commands::extract;
+use crate::commands::util;
+use crate::commands::util::BatchReport;
+use crate::config::{GlobalConfig, RefSyncConfig, Reference};
+use crate::mkv::{self, Track};
+use crate::scanner::{self, MatchInfo, ScanOptions};
+use crate::subtitle::ParsedSub;
+use anyhow::Result as AnyResult;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use subparse::timetypes::TimeDelta;
+
+pub struct RefSyncCommand<'a> {
+    global_conf: &'a GlobalConfig,
+    conf: RefSyncConfig,
+}
+
+impl<'a> RefSyncCommand<'a> {
+    pub fn new(global_conf: &'a GlobalConfig, conf: RefSyncConfig) -> Self {
+        RefSyncCommand { global_conf, conf }
+    }
+
+    pub fn run(&self) -> AnyResult<()> {
+        let mut report = BatchReport::default();
+        let matches = scanner::scan(ScanOptions::from_global_and_match_conf(
+            self.global_conf,
+            &self.conf.match_config,
+        ))?;
+        let matches = util::validate_sub_and_file_matches(
+            self.global_conf,
+            matches,
+            util::SUPPORTED_SUBTITLE_FORMATS,
+            &mut report,
+        )?;
+
+        let mut by_video: BTreeMap<PathBuf, Vec<MatchInfo>> = BTreeMap::new();
+        for m in matches {
+            by_video.entry(m.video_path.clone()).or_default().push(m);
+        }
+
+        for (video, mut subs) in by_video {
+            subs.sort_by(|a, b| a.sub_path.cmp(&b.sub_path));
+            let (reference, targets) = match self.reference(&video, subs) {
+                Ok(found) => found,
+                Err(e) if !self.global_conf.strict => {
+                    report.skip(&video, e);
+                    continue;
+                }
+                Err(e) => {
+                    return Err(e.context(format!("failed to read the reference of {:?}", video)));
+                }
+            };
+            let targets = targets
+                .iter()
+                .map(|m| m.sub_path.clone())
+                .collect::<Vec<_>>();
+            let mut parsed_subs = util::read_subs(
+                self.global_conf,
+                &targets,
+                &mut report,
+                self.conf.output_encoding,
+                |path| ParsedSub::read(path, self.conf.encoding, self.conf.fps),
+            )?;
+            for sub in &mut parsed_subs {
+                // Subs that are already in sync aren't written.
+                if self.align(sub, &reference) {
+                    sub.write()?;
+                    report.processed += 1;
+                }
+            }
+        }
+        report.finish()
+    }
+
+    /// Returns the cues of the reference of a video and the subs that are aligned to it.
+    fn reference(
+        &self,
+        video: &Path,
+        subs: Vec<MatchInfo>,
+    ) -> AnyResult<(Vec<Cue>, Vec<MatchInfo>)> {
+        let ext = match &self.conf.reference {
+            Reference::Extension(ext) => ext,
+            reference => return Ok((embedded_cues(video, reference)?, subs)),
+        };
+        let (references, targets): (Vec<_>, Vec<_>) = subs.into_iter().partition(|m| {
+            scanner::secondary_extension(&m.sub_path, self.conf.match_config.secondary_ext_policy)
+                .is_some_and(|secondary| secondary.eq_ignore_ascii_case(ext))
+        });
+        let reference = match references.as_slice() {
+            [reference] => reference,
+            [] => bail!("found no reference subtitle with the extension .{}", ext),
+            _ => bail!("found more than one subtitle with the extension .{}", ext),
+        };
+        let sub = ParsedSub::read(&reference.sub_path, self.conf.encoding, self.conf.fps)?;
+        let cues = sub
+            .entries
+            .iter()
+            .map(|entry| (entry.timespan.start.msecs(), entry.timespan.end.msecs()))
+            .collect();
+        Ok((cues, targets))
+    }
+
+    /// Shifts the cues of `sub` by the offsets that align them with `reference` and prints the
+    /// offsets. Returns false if all offsets are 0, i.e. nothing was changed.
+    fn align(&self, sub: &mut ParsedSub, reference: &[Cue]) -> bool {
+        let mut order = (0..sub.entries.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| sub.entries[i].timespan.start);
+        let cues = order
+            .iter()
+            .map(|&i| {
+                let span = sub.entries[i].timespan;
+                (span.start.msecs(), span.end.msecs())
+            })
+            .collect::<Vec<_>>();
+        let offsets = align::align(
+            reference,
+            &cues,
+            AlignOptions {
+                max_offset: self.conf.max_offset,
+                split_penalty: self.conf.split_penalty,
+            },
+        );
+
+        // Consecutive cues with the same offset are printed as one segment.
+        let mut segments: Vec<(usize, usize, i64)> = Vec::new();
+        for (position, (&i, &offset)) in order.iter().zip(&offsets).enumerate() {
+            sub.entries[i].timespan += TimeDelta::from_msecs(offset);
+            match segments.last_mut() {
+                Some((_, last, segment_offset)) if *segment_offset == offset => *last = position,
+                _ => segments.push((position, position, offset)),
+            }
+        }
+        let changed = offsets.iter().any(|&offset| offset != 0);
+        let segments = match segments.as_slice() {
+            [] => "no cues".to_string(),
+            [(_, _, 0)] => "offset 0ms, unchanged".to_string(),
+            [(_, _, offset)] => format!("offset {}ms", offset),
+            _ => segments
+                .iter()
+                .map(|(first, last, offset)| {
+                    format!("offset {}ms for cues {}-{}", offset, first + 1, last + 1)
+                })
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        println!(
+            "{}: {}",
+            sub.path.file_name().unwrap_or_default().to_string_lossy(),
+            segments
+        );
+        changed
+    }
+}
+
+/// Reads the cues of the embedded subtitle track of the video that the reference selects.
+fn embedded_cues(video: &Path, reference: &Reference) -> AnyResult<Vec<Cue>> {
+    let info = mkv::read_info(video)?;
+    let is_reference = |track: &&Track| match reference {
+        Reference::TrackNumber(number) => track.number == *number,
+        Reference::TrackLanguage(language) => language.eq_ignore_ascii_case(track.language()),
+        Reference::Extension(_) => false,
+    };
+    let track = info
+        .tracks
+        .iter()
+        .filter(|track| track.is_subtitle())
+        .find(is_reference)
+        .ok_or_else(|| anyhow!("has no matching subtitle track"))?;
+    let (_, blocks) = mkv::read_blocks(video, &[track.number])?;
+    Ok(blocks
+        .iter()
+        .map(|block| {
+            let span = extract::timespan(block);
+            (span.start.msecs(), span.end.msecs())
+        })
+        .collect())
+}
@@ -53,6 +53,9 @@ impl<'a> RenameCommand<'a> {
             return Ok(());
         }
 
+        let mismatches = util::duration_mismatches(&renames, self.conf.duration_check.as_ref());
+        util::check_durations(self.global_conf, &renames, &mismatches)?;
+
         if !self.global_conf.confirm {
             rename_subtitles(&renames)?;
             return Ok(());
@@ -64,6 +67,7 @@ impl<'a> RenameCommand<'a> {
             self.conf.video_area.as_ref(),
             self.global_conf.color,
             false,
+            &mismatches,
             self.line_editor.as_mut(),
         )?;
 
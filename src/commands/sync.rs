@@ -0,0 +1,115 @@
+use crate::commands::util;
+use crate::commands::util::BatchReport;
+use crate::config::{CueSelector, GlobalConfig, StretchConfig, SyncAnchor, SyncConfig};
+use crate::scanner::{self, AreaScan, ScanOptions, SecondaryExtensionPolicy};
+use crate::subtitle::ParsedSub;
+use anyhow::Result as AnyResult;
+
+pub struct SyncCommand<'a> {
+    global_conf: &'a GlobalConfig,
+    conf: SyncConfig,
+}
+
+impl<'a> SyncCommand<'a> {
+    pub fn new(global_conf: &'a GlobalConfig, conf: SyncConfig) -> Self {
+        SyncCommand { global_conf, conf }
+    }
+
+    pub fn run(&self) -> AnyResult<()> {
+        let mut report = BatchReport::default();
+        let matches = scanner::scan_subs_only(ScanOptions::from_global_conf(
+            self.global_conf,
+            None,
+            AreaScan::Normal,
+            None,
+            AreaScan::Normal,
+            SecondaryExtensionPolicy::Never,
+        ))?;
+        let matches = util::validate_sub_matches(self.global_conf, matches, &mut report)?;
+
+        let mut parsed_subs = util::read_subs(
+            self.global_conf,
+            &matches,
+            &mut report,
+            self.conf.output_encoding,
+            |path| ParsedSub::read(path, self.conf.encoding, self.conf.fps),
+        )?;
+
+        // Compute all transforms before writing anything so that a cue that can't be found
+        // in one of the files doesn't leave the batch half synchronized.
+        let transforms = parsed_subs
+            .iter()
+            .map(|sub| self.transform_for(sub))
+            .collect::<AnyResult<Vec<_>>>()?;
+
+        for (sub, (stretch, offset)) in parsed_subs.iter_mut().zip(transforms) {
+            println!(
+                "{}: offset {}ms, scale {:.6}",
+                sub.path.file_name().unwrap_or_default().to_string_lossy(),
+                offset,
+                stretch.factor
+            );
+            sub.transform(Some(&stretch), offset);
+            sub.write()?;
+            report.processed += 1;
+        }
+        report.finish()
+    }
+
+    /// Returns the stretch and offset that moves the first anchor cue to the first anchor time
+    /// and the second anchor cue to the second anchor time.
+    fn transform_for(&self, sub: &ParsedSub) -> AnyResult<(StretchConfig, i64)> {
+        let first = cue_start(sub, &self.conf.first)?;
+        let second = cue_start(sub, &self.conf.second)?;
+
+        if first == second {
+            bail!(
+                "{:?}: both anchor cues start at the same time ({}ms)",
+                sub.path,
+                first
+            );
+        }
+
+        let factor =
+            (self.conf.second.time - self.conf.first.time) as f64 / (second - first) as f64;
+        if factor <= 0.0 {
+            bail!(
+                "{:?}: the anchor times are not in the same order as the anchor cues",
+                sub.path
+            );
+        }
+
+        let stretch = StretchConfig {
+            factor,
+            anchor: first,
+            fps_conversion: None,
+        };
+        Ok((stretch, self.conf.first.time - first))
+    }
+}
+
+fn cue_start(sub: &ParsedSub, anchor: &SyncAnchor) -> AnyResult<i64> {
+    let entry = match &anchor.cue {
+        CueSelector::Index(index) => sub.entries.get(index - 1).ok_or_else(|| {
+            anyhow!(
+                "{:?}: cue {} does not exist (the file has {} cues)",
+                sub.path,
+                index,
+                sub.entries.len()
+            )
+        })?,
+        CueSelector::Text(text) => {
+            let text = text.to_lowercase();
+            sub.entries
+                .iter()
+                .find(|entry| {
+                    entry
+                        .line
+                        .as_ref()
+                        .is_some_and(|line| line.to_lowercase().contains(&text))
+                })
+                .ok_or_else(|| anyhow!("{:?}: found no cue containing {:?}", sub.path, text))?
+        }
+    };
+    Ok(entry.timespan.start.msecs())
+}
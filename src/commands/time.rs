@@ -1,10 +1,10 @@
 use crate::commands::util;
-use crate::config::{GlobalConfig, TimeConfig};
+use crate::commands::util::BatchReport;
+use crate::config::{GlobalConfig, StretchConfig, TimeConfig};
 use crate::scanner::{self, AreaScan, ScanOptions, SecondaryExtensionPolicy};
+use crate::subtitle::ParsedSub;
 use anyhow::Result as AnyResult;
-use std::fs;
-use subparse::SubtitleFile;
-use subparse::timetypes::TimeDelta;
+use std::collections::HashMap;
 
 pub struct TimeCommand<'a> {
     global_conf: &'a GlobalConfig,
@@ -17,6 +17,12 @@ impl<'a> TimeCommand<'a> {
     }
 
     pub fn run(&self) -> AnyResult<()> {
+        self.run_batch()?.finish()
+    }
+
+    /// Adjusts the timings and returns the report without printing the skipped files.
+    pub fn run_batch(&self) -> AnyResult<BatchReport> {
+        let mut report = BatchReport::default();
         let matches = scanner::scan_subs_only(ScanOptions::from_global_conf(
             self.global_conf,
             None,
@@ -25,37 +31,54 @@ impl<'a> TimeCommand<'a> {
             AreaScan::Normal,
             SecondaryExtensionPolicy::Never,
         ))?;
-        util::validate_sub_matches(self.global_conf, &matches)?;
-
-        let mut parsed_subs: Vec<SubtitleFile> = matches
-            .iter()
-            .map(|path| -> AnyResult<SubtitleFile> {
-                let content = fs::read(path)?;
-                let format = subparse::get_subtitle_format(path.extension(), &content)
-                    .ok_or_else(|| anyhow!("invalid subtitle format: {:?}", path.extension()))?;
-
-                subparse::parse_bytes(format, &content, Some(self.conf.encoding), self.conf.fps)
-                    .map_err(|e| anyhow!("failed to parse subtitle file: {:?}", e))
-            })
-            .collect::<AnyResult<_>>()?;
+        let matches = util::validate_sub_matches(self.global_conf, matches, &mut report)?;
 
-        for (i, sub) in parsed_subs.iter_mut().enumerate() {
-            let mut entries = sub
-                .get_subtitle_entries()
-                .map_err(|e| anyhow!("failed to get subtitle entries: {:?}", e))?;
+        let conversion = match &self.conf.stretch {
+            Some(StretchConfig {
+                fps_conversion: Some((from, to)),
+                ..
+            }) => Some((*from, *to)),
+            _ => None,
+        };
+        let video_fps = match (conversion, self.conf.fps) {
+            (None, None) => util::video_frame_rates(self.global_conf)?,
+            _ => HashMap::new(),
+        };
 
-            for entry in &mut entries {
-                entry.timespan += TimeDelta::from_msecs(self.conf.timing);
+        let mut parsed_subs = util::read_subs(
+            self.global_conf,
+            &matches,
+            &mut report,
+            self.conf.output_encoding,
+            |path| {
+                let (read_fps, write_fps) = conversion.unwrap_or_else(|| {
+                    let fps = self
+                        .conf
+                        .fps
+                        .or_else(|| video_fps.get(path).copied())
+                        .unwrap_or(25.0);
+                    (fps, fps)
+                });
+                ParsedSub::read_with_fps(path, self.conf.encoding, read_fps, write_fps)
+            },
+        )?;
+        if self.global_conf.verbose {
+            for sub in &parsed_subs {
+                if let Some(fps) = video_fps.get(&sub.path) {
+                    println!(
+                        "{}: {} fps of the video",
+                        sub.path.file_name().unwrap_or_default().to_string_lossy(),
+                        util::format_fps(*fps)
+                    );
+                }
             }
-            sub.update_subtitle_entries(&entries)
-                .map_err(|e| anyhow!("failed to update subtitle entries: {:?}", e))?;
-
-            let data = sub
-                .to_data()
-                .map_err(|e| anyhow!("failed to get subtitle data: {:?}", e))?;
+        }
 
-            fs::write(&matches[i], data)?;
+        for sub in &mut parsed_subs {
+            sub.transform(self.conf.stretch.as_ref(), self.conf.timing);
+            sub.write()?;
+            report.processed += 1;
         }
-        Ok(())
+        Ok(report)
     }
 }
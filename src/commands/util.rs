@@ -1,12 +1,19 @@
-use crate::config::GlobalConfig;
-use crate::scanner::{MatchInfo, MatchInfoType};
+use crate::config::{DurationCheck, GlobalConfig};
+use crate::probe::VideoInfo;
+use crate::scanner::{
+    self, AreaScan, MatchInfo, MatchInfoType, ScanOptions, SecondaryExtensionPolicy,
+};
+use crate::subtitle::ParsedSub;
 use anyhow::Result as AnyResult;
 use core::result::Result::Ok;
 use crossterm::style::Stylize;
+use encoding_rs::Encoding;
 use regex::Regex;
 use rustyline::DefaultEditor;
 use rustyline::error::ReadlineError;
+use std::collections::HashMap;
 use std::ffi::OsStr;
+use std::fmt;
 use std::io::{self, Write};
 use std::ops::Range;
 use std::path::{Path, PathBuf};
@@ -14,6 +21,9 @@ use std::path::{Path, PathBuf};
 // these are the formats that subparse currently supports
 pub static SUBPARSE_SUPPORTED_SUBTITLE_FORMATS: &[&str] = &["ssa", "ass", "sub", "srt", "idx"];
 
+// these are the formats that sub-batch can parse (subparse formats and our own WebVTT parser)
+pub static SUPPORTED_SUBTITLE_FORMATS: &[&str] = &["ssa", "ass", "sub", "srt", "idx", "vtt"];
+
 #[derive(Debug, PartialEq)]
 pub enum AskMatchAnswer {
     Yes,
@@ -28,6 +38,7 @@ pub fn ask_match_is_ok(
     video_area_regex: Option<&Regex>,
     color: bool,
     print_identical: bool,
+    mismatches: &[Option<DurationMismatch>],
     mut line_editor: Option<&mut DefaultEditor>,
 ) -> AnyResult<AskMatchAnswer> {
     fn print_file_parts(
@@ -73,7 +84,18 @@ pub fn ask_match_is_ok(
         .max_by(|a, b| a.cmp(b))
         .unwrap();
 
-    for rename in renames.iter() {
+    let print_mismatch = |mismatch: &Option<DurationMismatch>| {
+        if let Some(mismatch) = mismatch {
+            let line = format!("  ! {}", mismatch);
+            if color {
+                println!("{}", line.red());
+            } else {
+                println!("{}", line);
+            }
+        }
+    };
+
+    for (rename, mismatch) in renames.iter().zip(mismatches) {
         let padding = str::repeat(" ", longest_sub_length - rename.sub_file_name.len());
 
         let MatchInfoType::NumberMatch {
@@ -85,9 +107,13 @@ pub fn ask_match_is_ok(
         else {
             if print_identical {
                 println!(
-                    "{}{} -> {}",
-                    rename.sub_file_name, padding, rename.video_file_name
+                    "{}{} -> {}{}",
+                    rename.sub_file_name,
+                    padding,
+                    rename.video_file_name,
+                    video_summary(rename.video_info(), color)
                 );
+                print_mismatch(mismatch);
             }
             continue;
         };
@@ -108,7 +134,8 @@ pub fn ask_match_is_ok(
             video_number_range,
             color,
         );
-        println!();
+        println!("{}", video_summary(rename.video_info(), color));
+        print_mismatch(mismatch);
     }
 
     println!(
@@ -151,24 +178,411 @@ pub fn ask_match_is_ok(
     )
 }
 
-pub fn validate_sub_matches(global_conf: &GlobalConfig, matches: &[PathBuf]) -> AnyResult<()> {
-    if matches.is_empty() {
-        bail!("found no subtitle files in {:?}", &global_conf.path);
+/// Describes the video after its file name in the confirmation, e.g.
+/// ` [00:42:10.000, 23.976 fps, 2 subtitle tracks]`. Empty if the video couldn't be probed.
+fn video_summary(info: Option<&VideoInfo>, color: bool) -> String {
+    let Some(info) = info else {
+        return String::new();
+    };
+    let mut parts = Vec::new();
+    if let Some(duration) = info.duration {
+        parts.push(timestamp(duration));
+    }
+    if let Some(fps) = info.fps {
+        parts.push(format!("{} fps", format_fps(fps)));
+    }
+    parts.push(match info.subtitle_tracks() {
+        0 => "no subtitle tracks".to_string(),
+        1 => "1 subtitle track".to_string(),
+        n => format!("{} subtitle tracks", n),
+    });
+    let summary = format!(" [{}]", parts.join(", "));
+    if color {
+        summary.dark_grey().to_string()
+    } else {
+        summary
     }
-    validate_sub_extensions(matches)?;
-    Ok(())
 }
 
-pub fn validate_sub_and_file_matches(
+/// A pair that fails the duration check.
+#[derive(Debug)]
+pub enum DurationMismatch {
+    /// The last cue of the sub ends far before or after the end of its video, which usually
+    /// means that it was matched with the wrong video. Times are in milliseconds.
+    Duration { sub_end: i64, video_duration: i64 },
+    /// The sub couldn't be read, so it couldn't be checked.
+    Unreadable(String),
+}
+
+impl fmt::Display for DurationMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DurationMismatch::Duration {
+                sub_end,
+                video_duration,
+            } => {
+                let difference = sub_end - video_duration;
+                write!(
+                    f,
+                    "the last cue ends {} {} the end of the video ({})",
+                    timestamp(difference.abs()),
+                    if difference > 0 { "after" } else { "before" },
+                    timestamp(*video_duration)
+                )
+            }
+            DurationMismatch::Unreadable(e) => {
+                write!(f, "the subtitle can't be read to check its duration: {}", e)
+            }
+        }
+    }
+}
+
+/// Compares the end of the last cue of each sub with the duration of its video. Pairs within the
+/// tolerance, pairs where either time is unknown, and subs in formats that sub-batch can't read
+/// get `None`.
+pub fn duration_mismatches(
+    matches: &[MatchInfo],
+    check: Option<&DurationCheck>,
+) -> Vec<Option<DurationMismatch>> {
+    matches
+        .iter()
+        .map(|m| {
+            let check = check?;
+            if !has_supported_subtitle_formats(&[&m.sub_path], SUPPORTED_SUBTITLE_FORMATS) {
+                return None;
+            }
+            let info = m.video_info()?;
+            let video_duration = info.duration?;
+            let sub = match ParsedSub::read(&m.sub_path, check.encoding, info.fps.unwrap_or(25.0)) {
+                Ok(sub) => sub,
+                Err(e) => return Some(DurationMismatch::Unreadable(e.to_string())),
+            };
+            let sub_end = sub
+                .entries
+                .iter()
+                .map(|entry| entry.timespan.end.msecs())
+                .max()?;
+            ((sub_end - video_duration).abs() > check.tolerance).then_some(
+                DurationMismatch::Duration {
+                    sub_end,
+                    video_duration,
+                },
+            )
+        })
+        .collect()
+}
+
+/// Refuses pairs with a duration mismatch in `--strict` mode. Otherwise they are flagged in the
+/// confirmation, or printed as warnings if there is none.
+pub fn check_durations(
     global_conf: &GlobalConfig,
     matches: &[MatchInfo],
+    mismatches: &[Option<DurationMismatch>],
 ) -> AnyResult<()> {
-    validate_sub_and_file_matches_ignore_extensions(global_conf, matches)?;
-    let sub_files: Vec<&PathBuf> = matches.iter().map(|m| &m.sub_path).collect();
-    validate_sub_extensions(&sub_files)?;
+    let flagged = matches
+        .iter()
+        .zip(mismatches)
+        .filter_map(|(m, mismatch)| {
+            Some(format!(
+                "{} -> {}: {}",
+                m.sub_file_name,
+                m.video_file_name,
+                mismatch.as_ref()?
+            ))
+        })
+        .collect::<Vec<_>>();
+    if flagged.is_empty() {
+        return Ok(());
+    }
+    if global_conf.strict {
+        bail!(
+            "{} subtitle(s) failed the duration check:\n{}",
+            flagged.len(),
+            flagged.join("\n")
+        );
+    }
+    if !global_conf.confirm {
+        for line in flagged {
+            eprintln!("warning: {}", line);
+        }
+    }
     Ok(())
 }
 
+/// Formats the rows as a table with a header and columns that are padded to the same width.
+pub fn table(header: &[&str], rows: &[Vec<String>]) -> String {
+    let widths = (0..header.len())
+        .map(|i| {
+            rows.iter()
+                .map(|row| row[i].chars().count())
+                .chain([header[i].len()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+
+    let mut out = String::new();
+    let header = header.iter().map(|h| h.to_string()).collect::<Vec<_>>();
+    for row in std::iter::once(&header).chain(rows) {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        out += line.trim_end();
+        out += "\n";
+    }
+    out
+}
+
+/// Formats a frame rate with at most 3 decimals, e.g. 25 or 23.976.
+pub fn format_fps(fps: f64) -> String {
+    let fps = format!("{:.3}", fps);
+    fps.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// A subtitle file that a command skipped, and why.
+#[derive(Debug)]
+pub struct SkippedFile {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// The outcome of a command that processes many subtitle files.
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    pub processed: usize,
+    pub skipped: Vec<SkippedFile>,
+    /// Files that were attempted but failed. They are listed by the command itself.
+    pub failed: usize,
+}
+
+/// The error that is returned when some of the files in a batch were skipped. The process
+/// exits with status 2 instead of 1 for this error.
+#[derive(Debug)]
+pub struct PartialSuccess {
+    pub processed: usize,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+impl fmt::Display for PartialSuccess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "processed {} subtitle file(s) but ", self.processed)?;
+        match (self.skipped, self.failed) {
+            (skipped, 0) => write!(f, "skipped {}", skipped),
+            (0, failed) => write!(f, "{} failed", failed),
+            (skipped, failed) => write!(f, "skipped {} and {} failed", skipped, failed),
+        }
+    }
+}
+
+impl std::error::Error for PartialSuccess {}
+
+impl BatchReport {
+    pub fn skip(&mut self, path: &Path, reason: impl ToString) {
+        self.skipped.push(SkippedFile {
+            path: path.to_path_buf(),
+            reason: reason.to_string(),
+        });
+    }
+
+    /// Prints the skipped files and returns a `PartialSuccess` error if any files were skipped
+    /// or failed.
+    pub fn finish(self) -> AnyResult<()> {
+        if self.skipped.is_empty() && self.failed == 0 {
+            return Ok(());
+        }
+        if !self.skipped.is_empty() {
+            println!("skipped:");
+        }
+        for skipped in &self.skipped {
+            println!("  {}: {}", skipped.path.display(), skipped.reason);
+        }
+        Err(PartialSuccess {
+            processed: self.processed,
+            skipped: self.skipped.len(),
+            failed: self.failed,
+        }
+        .into())
+    }
+}
+
+/// Returns the subs that have a supported format. Subs with other formats are added to the
+/// report as skipped, or fail the command if `--strict` is set.
+pub fn validate_sub_matches(
+    global_conf: &GlobalConfig,
+    matches: Vec<PathBuf>,
+    report: &mut BatchReport,
+) -> AnyResult<Vec<PathBuf>> {
+    if matches.is_empty() {
+        bail!("found no subtitle files in {:?}", &global_conf.path);
+    }
+    if global_conf.strict {
+        validate_sub_extensions(&matches, SUPPORTED_SUBTITLE_FORMATS)?;
+        return Ok(matches);
+    }
+    let supported = skip_unsupported(matches, |m| m, SUPPORTED_SUBTITLE_FORMATS, report);
+    if supported.is_empty() {
+        bail!(
+            "found no subtitle files with any of the supported formats: {:?}",
+            SUPPORTED_SUBTITLE_FORMATS
+        );
+    }
+    Ok(supported)
+}
+
+/// Returns the matches where the sub has a supported format. Matches with other sub formats
+/// are added to the report as skipped, or fail the command if `--strict` is set.
+pub fn validate_sub_and_file_matches(
+    global_conf: &GlobalConfig,
+    matches: Vec<MatchInfo>,
+    supported_formats: &[&str],
+    report: &mut BatchReport,
+) -> AnyResult<Vec<MatchInfo>> {
+    validate_sub_and_file_matches_ignore_extensions(global_conf, &matches)?;
+    if global_conf.strict {
+        let sub_files: Vec<&PathBuf> = matches.iter().map(|m| &m.sub_path).collect();
+        validate_sub_extensions(&sub_files, supported_formats)?;
+        return Ok(matches);
+    }
+    let supported = skip_unsupported(matches, |m| &m.sub_path, supported_formats, report);
+    if supported.is_empty() {
+        bail!(
+            "found no subtitle files with any of the supported formats: {:?}",
+            supported_formats
+        );
+    }
+    Ok(supported)
+}
+
+/// Returns the frame rates of the videos that MicroDVD (.sub) subs are matched with, by sub
+/// path. Subs whose video has no known frame rate are left out.
+pub fn video_frame_rates(global_conf: &GlobalConfig) -> AnyResult<HashMap<PathBuf, f64>> {
+    let matches = scanner::scan(ScanOptions::from_global_conf(
+        global_conf,
+        None,
+        AreaScan::Normal,
+        None,
+        AreaScan::Normal,
+        SecondaryExtensionPolicy::Maybe,
+    ))?;
+    Ok(matches
+        .iter()
+        .filter(|m| m.sub_path.extension().and_then(OsStr::to_str) == Some("sub"))
+        .filter_map(|m| Some((m.sub_path.clone(), m.video_info()?.fps?)))
+        .collect())
+}
+
+/// Returns the durations in milliseconds of the videos that subs are matched with, by sub path.
+/// Subs whose video has no known duration are left out.
+pub fn video_durations(global_conf: &GlobalConfig) -> AnyResult<HashMap<PathBuf, i64>> {
+    let matches = scanner::scan(ScanOptions::from_global_conf(
+        global_conf,
+        None,
+        AreaScan::Normal,
+        None,
+        AreaScan::Normal,
+        SecondaryExtensionPolicy::Maybe,
+    ))?;
+    Ok(matches
+        .iter()
+        .filter_map(|m| Some((m.sub_path.clone(), m.video_info()?.duration?)))
+        .collect())
+}
+
+/// Reads all files with `read`. In `--strict` mode the first error fails the command, otherwise
+/// files that can't be read are added to the report as skipped.
+pub fn read_all<T>(
+    global_conf: &GlobalConfig,
+    paths: &[PathBuf],
+    report: &mut BatchReport,
+    read: impl Fn(&Path) -> AnyResult<T>,
+) -> AnyResult<Vec<T>> {
+    let mut read_files = Vec::new();
+    for path in paths {
+        match read(path) {
+            Ok(file) => read_files.push(file),
+            Err(e) if !global_conf.strict => report.skip(path, e),
+            Err(e) => return Err(e.context(format!("failed to read {:?}", path))),
+        }
+    }
+    if read_files.is_empty() && !paths.is_empty() {
+        bail!("none of the files could be read");
+    }
+    Ok(read_files)
+}
+
+/// Reads the subtitle files with `read_all` and sets their output path, encoding and backup
+/// suffix. The encoding of each file is printed in verbose mode.
+pub fn read_subs(
+    global_conf: &GlobalConfig,
+    paths: &[PathBuf],
+    report: &mut BatchReport,
+    output_encoding: Option<&'static Encoding>,
+    read: impl Fn(&Path) -> AnyResult<ParsedSub>,
+) -> AnyResult<Vec<ParsedSub>> {
+    let mut subs = read_all(global_conf, paths, report, read)?;
+    for sub in &mut subs {
+        sub.output_encoding = output_encoding;
+        sub.backup = global_conf.backup.clone();
+        sub.out_path = output_path(global_conf, &sub.path);
+        if global_conf.verbose {
+            println!(
+                "{}: {} ({})",
+                sub.path.file_name().unwrap_or_default().to_string_lossy(),
+                sub.encoding.name(),
+                sub.detection
+            );
+        }
+    }
+    Ok(subs)
+}
+
+/// Returns the path that a changed version of the subtitle at `path` should be written to. This
+/// is `path` itself unless --out-dir is set.
+pub fn output_path(global_conf: &GlobalConfig, path: &Path) -> PathBuf {
+    match (&global_conf.out_dir, path.file_name()) {
+        (Some(out_dir), Some(file_name)) => out_dir.join(file_name),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Formats milliseconds as hh:mm:ss.mmm.
+pub fn timestamp(msecs: i64) -> String {
+    let sign = if msecs < 0 { "-" } else { "" };
+    let msecs = msecs.abs();
+    format!(
+        "{}{:02}:{:02}:{:02}.{:03}",
+        sign,
+        msecs / 3_600_000,
+        msecs / 60_000 % 60,
+        msecs / 1000 % 60,
+        msecs % 1000
+    )
+}
+
+fn skip_unsupported<T>(
+    matches: Vec<T>,
+    sub_path: impl Fn(&T) -> &PathBuf,
+    supported_formats: &[&str],
+    report: &mut BatchReport,
+) -> Vec<T> {
+    let (supported, unsupported): (Vec<T>, Vec<T>) = matches
+        .into_iter()
+        .partition(|m| has_supported_subtitle_formats(&[sub_path(m)], supported_formats));
+    for m in &unsupported {
+        let path = sub_path(m);
+        let ext = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_string())
+            .unwrap_or_default();
+        report.skip(path, format!("unsupported subtitle format (.{})", ext));
+    }
+    supported
+}
+
 pub fn validate_sub_and_file_matches_ignore_extensions(
     global_conf: &GlobalConfig,
     matches: &[MatchInfo],
@@ -182,22 +596,28 @@ pub fn validate_sub_and_file_matches_ignore_extensions(
     Ok(())
 }
 
-fn validate_sub_extensions(sub_files: &[impl AsRef<Path>]) -> AnyResult<()> {
-    if !has_subparse_supported_subtitle_formats(sub_files) {
+fn validate_sub_extensions(
+    sub_files: &[impl AsRef<Path>],
+    supported_formats: &[&str],
+) -> AnyResult<()> {
+    if !has_supported_subtitle_formats(sub_files, supported_formats) {
         bail!(
             "command supports only the following subtitle formats: {:?}",
-            SUBPARSE_SUPPORTED_SUBTITLE_FORMATS
+            supported_formats
         );
     }
     Ok(())
 }
 
-fn has_subparse_supported_subtitle_formats(matches: &[impl AsRef<Path>]) -> bool {
+fn has_supported_subtitle_formats(
+    matches: &[impl AsRef<Path>],
+    supported_formats: &[&str],
+) -> bool {
     matches.iter().all(|m| {
         m.as_ref()
             .extension()
             .and_then(OsStr::to_str)
-            .map(|ext| SUBPARSE_SUPPORTED_SUBTITLE_FORMATS.contains(&ext))
+            .map(|ext| supported_formats.contains(&ext))
             == Some(true)
     })
 }
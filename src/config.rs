@@ -4,9 +4,8 @@ use clap::ArgMatches;
 use clap::{App, SubCommand};
 use clap::{AppSettings, Arg};
 use encoding_rs::Encoding;
-use encoding_rs::UTF_8;
 use regex::Regex;
-use std::num::{ParseFloatError, ParseIntError};
+use std::num::ParseFloatError;
 use std::path::PathBuf;
 use std::str::FromStr;
 
@@ -16,6 +15,12 @@ pub struct GlobalConfig {
     pub path: PathBuf,
     pub confirm: bool,
     pub color: bool,
+    pub strict: bool,
+    pub verbose: bool,
+    /// If set subtitles that are changed are first copied to a file with this suffix.
+    pub backup: Option<String>,
+    /// If set changed subtitles are written to this directory instead of replacing the originals.
+    pub out_dir: Option<PathBuf>,
     pub sub_filter: Option<Regex>,
     pub video_filter: Option<Regex>,
 }
@@ -24,6 +29,16 @@ pub struct GlobalConfig {
 pub enum CommandConfig {
     Rename(MatchFilesConfig),
     Time(TimeConfig),
+    Sync(SyncConfig),
+    Convert(ConvertConfig),
+    Clean(CleanConfig),
+    Merge(MergeConfig),
+    Info(InfoConfig),
+    Lint(LintConfig),
+    Fix(FixConfig),
+    Extract(ExtractConfig),
+    Diagnose(DiagnoseConfig),
+    RefSync(RefSyncConfig),
     Alass(AlassConfig),
     Mpv,
 }
@@ -35,19 +50,254 @@ pub struct MatchFilesConfig {
     pub video_area: Option<Regex>,
     pub video_area_scan: AreaScan,
     pub secondary_ext_policy: SecondaryExtensionPolicy,
+    /// `None` if the command doesn't check durations.
+    pub duration_check: Option<DurationCheck>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DurationCheck {
+    /// Pairs whose last cue ends more than this many milliseconds before or after the end of the
+    /// video are flagged.
+    pub tolerance: i64,
+    /// The encoding that the subs are read with to find their last cue.
+    pub encoding: Option<&'static Encoding>,
 }
 
 #[derive(Debug)]
 pub struct TimeConfig {
     pub timing: i64,
-    pub encoding: &'static Encoding,
+    pub stretch: Option<StretchConfig>,
+    /// The encoding of the subs, detected per file if `None`.
+    pub encoding: Option<&'static Encoding>,
+    /// The encoding to write the subs with, the encoding they were read with if `None`.
+    pub output_encoding: Option<&'static Encoding>,
+    /// The frame rate of MicroDVD subs. If `None` the frame rate of the matched video is used,
+    /// or 25 if it has none.
+    pub fps: Option<f64>,
+}
+
+/// Scales all timings around `anchor` (in milliseconds) by `factor`.
+#[derive(Debug, Clone)]
+pub struct StretchConfig {
+    pub factor: f64,
+    pub anchor: i64,
+    /// Set when the stretch is a frame rate conversion (from, to). Frame based subtitle formats
+    /// are then read with the first and written with the second frame rate.
+    pub fps_conversion: Option<(f64, f64)>,
+}
+
+#[derive(Debug)]
+pub struct SyncConfig {
+    pub first: SyncAnchor,
+    pub second: SyncAnchor,
+    /// The encoding of the subs, detected per file if `None`.
+    pub encoding: Option<&'static Encoding>,
+    /// The encoding to write the subs with, the encoding they were read with if `None`.
+    pub output_encoding: Option<&'static Encoding>,
+    pub fps: f64,
+}
+
+/// A cue in the subtitle and the time (in milliseconds) that the cue should start at.
+#[derive(Debug)]
+pub struct SyncAnchor {
+    pub cue: CueSelector,
+    pub time: i64,
+}
+
+#[derive(Debug)]
+pub enum CueSelector {
+    /// 1-based index of the cue in the subtitle file.
+    Index(usize),
+    /// The first cue that contains this text (case-insensitive).
+    Text(String),
+}
+
+#[derive(Debug)]
+pub struct ConvertConfig {
+    pub to: ConvertFormat,
+    pub replace: bool,
+    pub font: String,
+    pub font_size: u32,
+    /// The encoding of the subs, detected per file if `None`.
+    pub encoding: Option<&'static Encoding>,
+    /// The encoding to write the subs with, the encoding they were read with if `None`.
+    pub output_encoding: Option<&'static Encoding>,
     pub fps: f64,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ConvertFormat {
+    Srt,
+    Ass,
+    Vtt,
+}
+
+impl ConvertFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ConvertFormat::Srt => "srt",
+            ConvertFormat::Ass => "ass",
+            ConvertFormat::Vtt => "vtt",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CleanConfig {
+    pub rules: Vec<CleanRule>,
+    /// Custom patterns that are removed from the text.
+    pub patterns: Vec<Regex>,
+    pub encoding: Option<&'static Encoding>,
+    pub output_encoding: Option<&'static Encoding>,
+    pub fps: f64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CleanRule {
+    /// Hearing impaired annotations like [DOOR SLAMS] and (laughs).
+    Sdh,
+    /// Speaker labels like JOHN: at the start of a line.
+    Speakers,
+    /// HTML tags and {\...} override tags.
+    Tags,
+    /// Lines with music notes.
+    Music,
+}
+
+#[derive(Debug)]
+pub struct MergeConfig {
+    /// The secondary extension (e.g. en) of the subs shown at the top.
+    pub top: String,
+    /// The secondary extension of the subs shown at the bottom.
+    pub bottom: String,
+    pub format: MergeFormat,
+    /// Cues that start within this many milliseconds of each other are joined.
+    pub tolerance: i64,
+    /// The output file name template.
+    pub name: String,
+    pub font: String,
+    pub font_size: u32,
+    /// RGB colors of the top and bottom styles.
+    pub top_color: u32,
+    pub bottom_color: u32,
+    pub encoding: Option<&'static Encoding>,
+    pub output_encoding: Option<&'static Encoding>,
+    pub fps: f64,
+    pub match_config: MatchFilesConfig,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MergeFormat {
+    /// An ASS file with a style for each language.
+    Ass,
+    /// An SRT file with the top lines stacked above the bottom lines.
+    Srt,
+}
+
+#[derive(Debug)]
+pub struct InfoConfig {
+    pub json: bool,
+    pub encoding: Option<&'static Encoding>,
+    pub fps: f64,
+}
+
+#[derive(Debug)]
+pub struct LintConfig {
+    /// The maximum number of characters per second.
+    pub max_cps: f64,
+    /// The maximum number of characters in a line.
+    pub max_line_length: usize,
+    /// The length of the video in milliseconds, which overrides the durations of the matched
+    /// videos. Cues that end after it are reported.
+    pub video_length: Option<i64>,
+    pub encoding: Option<&'static Encoding>,
+    pub fps: f64,
+}
+
+#[derive(Debug)]
+pub struct FixConfig {
+    /// The minimum time in milliseconds between the end of a cue and the start of the next one.
+    pub min_gap: i64,
+    /// The minimum and maximum duration of a cue in milliseconds.
+    pub min_duration: Option<i64>,
+    pub max_duration: Option<i64>,
+    /// If set the changes are only printed.
+    pub dry_run: bool,
+    pub encoding: Option<&'static Encoding>,
+    pub output_encoding: Option<&'static Encoding>,
+    pub fps: f64,
+}
+
+#[derive(Debug)]
+pub struct ExtractConfig {
+    /// If set the subtitle tracks are only listed.
+    pub list: bool,
+    /// The languages of the tracks to extract. All languages if empty.
+    pub languages: Vec<String>,
+    /// The numbers of the tracks to extract. All tracks if empty.
+    pub tracks: Vec<u64>,
+}
+
+#[derive(Debug)]
+pub struct RefSyncConfig {
+    pub reference: Reference,
+    /// The largest offset in milliseconds that a cue is moved by.
+    pub max_offset: i64,
+    /// How much a change of the offset must improve the overlap with the reference in
+    /// milliseconds. `None` if the whole sub gets the same offset.
+    pub split_penalty: Option<i64>,
+    pub encoding: Option<&'static Encoding>,
+    pub output_encoding: Option<&'static Encoding>,
+    pub fps: f64,
+    pub match_config: MatchFilesConfig,
+}
+
+/// The correctly timed subtitle that the other subs of a video are aligned to.
+#[derive(Debug)]
+pub enum Reference {
+    /// The sub with this secondary extension (e.g. en for ep01.en.srt).
+    Extension(String),
+    /// The embedded subtitle track of the video with this number.
+    TrackNumber(u64),
+    /// The first embedded subtitle track of the video with this language.
+    TrackLanguage(String),
+}
+
+/// The program that the alass command aligns subs with.
+#[derive(Debug, Clone)]
+pub enum AlignBackend {
+    Alass,
+    Ffsubsync,
+    /// A command line whose arguments contain `{video}`, `{sub}` and `{out}` placeholders.
+    Template(Vec<String>),
+}
+
+#[derive(Debug)]
+pub struct DiagnoseConfig {
+    /// How far the ratio of the end of the last cue to the duration of the video may be from a
+    /// frame rate conversion, as a fraction of the distance of the conversion's ratio to 1.
+    pub tolerance: f64,
+    /// If set the suggested stretch is applied without asking.
+    pub apply: bool,
+    pub encoding: Option<&'static Encoding>,
+    pub match_config: MatchFilesConfig,
+}
+
 #[derive(Debug, Clone)]
 pub struct AlassConfig {
     pub flags: Vec<String>,
-    pub no_parallel: bool,
+    pub backend: AlignBackend,
+    /// The directory that the output of the aligner is logged to for each sub.
+    pub log_dir: Option<PathBuf>,
+    /// If set the aligned subs are written next to the originals with this suffix before the
+    /// extension instead of replacing them.
+    pub suffix: Option<String>,
+    /// How many subs are aligned at the same time, `None` for one per CPU.
+    pub jobs: Option<usize>,
+    /// The time in milliseconds after which an aligner run is stopped.
+    pub timeout: Option<i64>,
+    /// If set pairs are aligned even if they are unchanged since their last alignment.
+    pub force: bool,
     pub match_config: MatchFilesConfig,
 }
 
@@ -55,8 +305,10 @@ impl TimeConfig {
     pub fn timing(timing: i64) -> Self {
         Self {
             timing,
-            encoding: UTF_8,
-            fps: 25.0,
+            stretch: None,
+            encoding: None,
+            output_encoding: None,
+            fps: None,
         }
     }
 }
@@ -83,6 +335,45 @@ impl GlobalConfig {
                         "If set no confirmation prompt is shown before applying file operations.",
                     ),
             )
+            .arg(
+                Arg::with_name("strict")
+                    .long("strict")
+                    .takes_value(false)
+                    .help(
+                        "If set the command fails if any targeted subtitle has an unsupported \
+                        format or can't be read. By default such subtitles are skipped, the \
+                        other subtitles are processed and the exit status is 2.",
+                    ),
+            )
+            .arg(
+                Arg::with_name("out_dir")
+                    .long("out-dir")
+                    .takes_value(true)
+                    .help(
+                        "If set subtitles that are changed or converted are written to this \
+                        directory (with their original file names) and the originals are left \
+                        untouched. The directory is created if it doesn't exist.",
+                    ),
+            )
+            .arg(
+                Arg::with_name("backup")
+                    .long("backup")
+                    .takes_value(true)
+                    .min_values(0)
+                    .require_equals(true)
+                    .value_name("SUFFIX")
+                    .help(
+                        "If set the original of each subtitle that is changed is kept in a file \
+                        with the suffix appended to its name (e.g. sub.srt.bak). The suffix \
+                        defaults to .bak.",
+                    ),
+            )
+            .arg(
+                Arg::with_name("verbose")
+                    .long("verbose")
+                    .takes_value(false)
+                    .help("If set the detected encoding of each subtitle is printed."),
+            )
             .arg(
                 Arg::with_name("no_color")
                     .long("no-color")
@@ -112,7 +403,8 @@ impl GlobalConfig {
             .subcommand(
                 SubCommand::with_name("rename")
                     .about("Renames subtitle files to match the corresponding video file.")
-                    .common_match_args(),
+                    .common_match_args()
+                    .duration_check_args(),
             )
             .subcommand(
                 SubCommand::with_name("time")
@@ -120,23 +412,447 @@ impl GlobalConfig {
                     // set this for the entire subcommand. https://github.com/clap-rs/clap/issues/1437
                     .settings(&[AppSettings::AllowLeadingHyphen])
                     .about(
-                        "Adjusts the timing of all subs. The value is specified in milliseconds, \
-                         and can be negative.",
+                        "Adjusts the timing of all subs. The value is specified in milliseconds \
+                         (e.g. 1500), with units (e.g. 1m2.5s, 300ms, 12f where f is frames at \
+                         --fps) or as a timestamp (e.g. 00:00:01,200), and can be negative.",
+                    )
+                    .arg(
+                        Arg::with_name("time")
+                            .required_unless_one(&["stretch", "fps_from"])
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("stretch")
+                            .long("stretch")
+                            .takes_value(true)
+                            .conflicts_with("fps_from")
+                            .help(
+                                "Scales all timings by this factor (e.g. 1.001). The scaling is \
+                                done around the --anchor time and before the time value is added.",
+                            ),
+                    )
+                    .arg(
+                        Arg::with_name("fps_from")
+                            .long("fps-from")
+                            .takes_value(true)
+                            .requires("fps_to")
+                            .help(
+                                "Converts the timings from subs made for a video with this frame \
+                                rate (e.g. 25) to the frame rate given by --fps-to.",
+                            ),
+                    )
+                    .arg(
+                        Arg::with_name("fps_to")
+                            .long("fps-to")
+                            .takes_value(true)
+                            .requires("fps_from")
+                            .help("The frame rate to convert timings to (e.g. 23.976)."),
+                    )
+                    .arg(
+                        Arg::with_name("anchor")
+                            .long("anchor")
+                            .takes_value(true)
+                            .help(
+                                "The time that stays fixed when timings are stretched. Takes \
+                                the same formats as the time value. Defaults to 0.",
+                            ),
+                    )
+                    .encoding_and_fps_args()
+                    .output_encoding_arg(),
+            )
+            .subcommand(
+                SubCommand::with_name("sync")
+                    .about(
+                        "Synchronizes all subs using two cues and the times they should start at. \
+                        The timings are shifted and stretched so that both cues end up at their \
+                        given times.",
+                    )
+                    .arg(sync_anchor_arg("first"))
+                    .arg(sync_anchor_arg("second"))
+                    .encoding_and_fps_args()
+                    .output_encoding_arg(),
+            )
+            .subcommand(
+                SubCommand::with_name("convert")
+                    .about(
+                        "Converts all subs to another subtitle format. The converted subs are \
+                        written next to the original subs with the extension of the new format.",
+                    )
+                    .arg(
+                        Arg::with_name("to")
+                            .long("to")
+                            .takes_value(true)
+                            .required(true)
+                            .possible_values(&["srt", "ass", "vtt"])
+                            .help("The subtitle format to convert to."),
+                    )
+                    .arg(
+                        Arg::with_name("replace")
+                            .long("replace")
+                            .takes_value(false)
+                            .help("If set the original subs are deleted after conversion."),
+                    )
+                    .arg(
+                        Arg::with_name("font")
+                            .long("font")
+                            .takes_value(true)
+                            .default_value("Arial")
+                            .help("The font of the default style when converting to ass."),
+                    )
+                    .arg(
+                        Arg::with_name("font_size")
+                            .long("font-size")
+                            .takes_value(true)
+                            .default_value("72")
+                            .help(
+                                "The font size of the default style when converting to ass \
+                                (the script resolution is 1920x1080).",
+                            ),
+                    )
+                    .encoding_and_fps_args()
+                    .output_encoding_arg(),
+            )
+            .subcommand(
+                SubCommand::with_name("clean")
+                    .about(
+                        "Removes unwanted text from all subs. Lines that become empty are \
+                        removed, and so are cues that have no text left.",
+                    )
+                    .arg(
+                        Arg::with_name("rules")
+                            .long("rules")
+                            .takes_value(true)
+                            .use_delimiter(true)
+                            .possible_values(&["sdh", "speakers", "tags", "music", "none"])
+                            .default_value("sdh,speakers,tags,music")
+                            .help(
+                                "Comma separated list of cleanup rules. sdh: hearing impaired \
+                                annotations like [DOOR SLAMS] and (laughs), speakers: speaker \
+                                labels like JOHN: at the start of lines, tags: HTML tags like \
+                                <i> and <font> and {\\...} override tags, music: lines with \
+                                music notes. Use none to only remove --pattern matches.",
+                            ),
+                    )
+                    .arg(
+                        Arg::with_name("pattern")
+                            .long("pattern")
+                            .takes_value(true)
+                            .multiple(true)
+                            .number_of_values(1)
+                            .help(
+                                "A regex for text to remove from all lines. Can be given \
+                                multiple times.",
+                            ),
+                    )
+                    .encoding_and_fps_args()
+                    .output_encoding_arg(),
+            )
+            .subcommand(
+                SubCommand::with_name("merge")
+                    .about(
+                        "Merges two subs of different languages that are matched with the same \
+                        video file into one bilingual subtitle. The subs are told apart by their \
+                        secondary extensions (e.g. ep01.en.srt and ep01.jp.srt).",
+                    )
+                    .arg(
+                        Arg::with_name("top")
+                            .long("top")
+                            .takes_value(true)
+                            .required(true)
+                            .help("The secondary extension of the subs shown at the top (e.g. en)."),
+                    )
+                    .arg(
+                        Arg::with_name("bottom")
+                            .long("bottom")
+                            .takes_value(true)
+                            .required(true)
+                            .help(
+                                "The secondary extension of the subs shown at the bottom (e.g. jp).",
+                            ),
+                    )
+                    .arg(
+                        Arg::with_name("format")
+                            .long("format")
+                            .takes_value(true)
+                            .possible_values(&["ass", "srt"])
+                            .default_value("ass")
+                            .help(
+                                "ass: one style for each language, with the top subs at the top \
+                                of the screen. srt: the top lines are stacked above the bottom \
+                                lines in the same cue.",
+                            ),
+                    )
+                    .arg(
+                        Arg::with_name("tolerance")
+                            .long("tolerance")
+                            .takes_value(true)
+                            .default_value("500ms")
+                            .help(
+                                "Cues that start within this time of each other are joined: they \
+                                are put in the same cue for srt and get the same timing for ass.",
+                            ),
+                    )
+                    .arg(
+                        Arg::with_name("name")
+                            .long("name")
+                            .takes_value(true)
+                            .default_value("{video}.{top}-{bottom}.{ext}")
+                            .help(
+                                "The file name of the merged subs. {video} is replaced with the \
+                                video file name without extension, {top} and {bottom} with the \
+                                secondary extensions and {ext} with the format.",
+                            ),
+                    )
+                    .arg(
+                        Arg::with_name("font")
+                            .long("font")
+                            .takes_value(true)
+                            .default_value("Arial")
+                            .help("The font of both styles (ass only)."),
+                    )
+                    .arg(
+                        Arg::with_name("font_size")
+                            .long("font-size")
+                            .takes_value(true)
+                            .default_value("64")
+                            .help("The font size of both styles (ass only)."),
+                    )
+                    .arg(
+                        Arg::with_name("top_color")
+                            .long("top-color")
+                            .takes_value(true)
+                            .default_value("FFFFFF")
+                            .help("The RGB hex color of the top style (ass only)."),
+                    )
+                    .arg(
+                        Arg::with_name("bottom_color")
+                            .long("bottom-color")
+                            .takes_value(true)
+                            .default_value("FFFF80")
+                            .help("The RGB hex color of the bottom style (ass only)."),
+                    )
+                    .encoding_and_fps_args()
+                    .output_encoding_arg()
+                    .common_match_args(),
+            )
+            .subcommand(
+                SubCommand::with_name("info")
+                    .about(
+                        "Prints the format, encoding, number of cues, first and last cue time, \
+                        overlapping cues and secondary extension of all subs.",
+                    )
+                    .arg(
+                        Arg::with_name("json")
+                            .long("json")
+                            .takes_value(false)
+                            .help("If set the info is printed as JSON instead of a table."),
+                    )
+                    .encoding_and_fps_args(),
+            )
+            .subcommand(
+                SubCommand::with_name("lint")
+                    .about(
+                        "Reports timing and readability problems in all subs: cues that are out \
+                        of order, have a negative start time or a duration of zero or less, \
+                        overlap, end after the video, are read too fast or have too long lines. \
+                        Exits with status 1 if any problems are found.",
+                    )
+                    .arg(
+                        Arg::with_name("max_cps")
+                            .long("max-cps")
+                            .takes_value(true)
+                            .default_value("25")
+                            .help("The maximum number of characters per second."),
+                    )
+                    .arg(
+                        Arg::with_name("max_line_length")
+                            .long("max-line-length")
+                            .takes_value(true)
+                            .default_value("42")
+                            .help("The maximum number of characters in a line."),
+                    )
+                    .arg(
+                        Arg::with_name("video_length")
+                            .long("video-length")
+                            .takes_value(true)
+                            .help(
+                                "The length of the video (e.g. 42m10s or 00:42:10.000). Cues \
+                                that end after it are reported. By default each sub is checked \
+                                against the duration of its matched Matroska or MP4 video.",
+                            ),
+                    )
+                    .encoding_and_fps_args(),
+            )
+            .subcommand(
+                SubCommand::with_name("fix")
+                    .about(
+                        "Repairs common timing problems in all subs: sorts the cues, moves \
+                        negative start times to 00:00:00, merges adjacent cues with the same \
+                        text and shortens cues that overlap the next cue. Each change is printed.",
+                    )
+                    .arg(
+                        Arg::with_name("min_gap")
+                            .long("min-gap")
+                            .takes_value(true)
+                            .default_value("0")
+                            .help(
+                                "The minimum time between the end of a cue and the start of the \
+                                next one (e.g. 80ms or 2f). Cues that end later are shortened.",
+                            ),
+                    )
+                    .arg(
+                        Arg::with_name("min_duration")
+                            .long("min-duration")
+                            .takes_value(true)
+                            .help(
+                                "The minimum duration of a cue. Shorter cues are extended, but \
+                                not past the next cue.",
+                            ),
+                    )
+                    .arg(
+                        Arg::with_name("max_duration")
+                            .long("max-duration")
+                            .takes_value(true)
+                            .help("The maximum duration of a cue. Longer cues are shortened."),
+                    )
+                    .arg(
+                        Arg::with_name("dry_run")
+                            .long("dry-run")
+                            .takes_value(false)
+                            .help("If set the changes are printed but the subs are not written."),
+                    )
+                    .encoding_and_fps_args()
+                    .output_encoding_arg(),
+            )
+            .subcommand(
+                SubCommand::with_name("extract")
+                    .about(
+                        "Extracts the SRT and ASS subtitle tracks of all Matroska (.mkv) videos \
+                        to <video>.<language>.<ext> files next to the videos.",
+                    )
+                    .arg(
+                        Arg::with_name("list")
+                            .long("list")
+                            .takes_value(false)
+                            .help(
+                                "If set the subtitle tracks of the videos are listed with their \
+                                number, language and codec but not extracted.",
+                            ),
+                    )
+                    .arg(
+                        Arg::with_name("lang")
+                            .long("lang")
+                            .takes_value(true)
+                            .multiple(true)
+                            .use_delimiter(true)
+                            .help(
+                                "Only extract tracks with these languages (e.g. eng,jpn). The \
+                                language codes are shown by --list.",
+                            ),
+                    )
+                    .arg(
+                        Arg::with_name("track")
+                            .long("track")
+                            .takes_value(true)
+                            .multiple(true)
+                            .use_delimiter(true)
+                            .help("Only extract the tracks with these numbers (e.g. 3,4)."),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("ref-sync")
+                    .about(
+                        "Aligns the subs of each video to a correctly timed reference subtitle of \
+                        the same video, using only the timings of the cues. The reference is \
+                        either the sub with the secondary extension given by --ref-ext or an \
+                        embedded track of the Matroska video given by --ref-track.",
+                    )
+                    .common_match_args()
+                    .arg(
+                        Arg::with_name("ref_ext")
+                            .long("ref-ext")
+                            .takes_value(true)
+                            .required_unless("ref_track")
+                            .conflicts_with("ref_track")
+                            .help(
+                                "The secondary extension of the reference subs (e.g. en for \
+                                ep01.en.srt).",
+                            ),
+                    )
+                    .arg(
+                        Arg::with_name("ref_track")
+                            .long("ref-track")
+                            .takes_value(true)
+                            .help(
+                                "The number (e.g. 3) or language (e.g. eng) of the embedded \
+                                subtitle track to use as the reference. See extract --list.",
+                            ),
+                    )
+                    .arg(
+                        Arg::with_name("max_offset")
+                            .long("max-offset")
+                            .takes_value(true)
+                            .default_value("5m")
+                            .help("The largest time that a cue is moved by in either direction."),
+                    )
+                    .arg(
+                        Arg::with_name("split_penalty")
+                            .long("split-penalty")
+                            .takes_value(true)
+                            .default_value("5s")
+                            .help(
+                                "The offset only changes between two cues (e.g. at a commercial \
+                                break) if that increases the time that the cues overlap the \
+                                reference by at least this much.",
+                            ),
+                    )
+                    .arg(
+                        Arg::with_name("no_split")
+                            .long("no-split")
+                            .takes_value(false)
+                            .conflicts_with("split_penalty")
+                            .help("If set all cues of a sub are moved by the same offset."),
+                    )
+                    .encoding_and_fps_args()
+                    .output_encoding_arg(),
+            )
+            .subcommand(
+                SubCommand::with_name("diagnose")
+                    .about(
+                        "Compares the end of the last cue of each sub with the duration of its \
+                        video to find subs that were made for a release with a different frame \
+                        rate (e.g. 25 instead of 23.976 fps), and suggests the stretch that fixes \
+                        them. The suggested stretch can then be applied to all subs.",
+                    )
+                    .common_match_args()
+                    .arg(
+                        Arg::with_name("tolerance")
+                            .long("tolerance")
+                            .takes_value(true)
+                            .default_value("10")
+                            .help(
+                                "How far the ratio of the last cue to the video duration may be \
+                                from the ratio of two frame rates, in percent of the distance of \
+                                that ratio to 1 (e.g. 10 allows 0.959 +- 0.0041 for 25 to 23.976 \
+                                fps).",
+                            ),
+                    )
+                    .arg(
+                        Arg::with_name("apply")
+                            .long("apply")
+                            .takes_value(false)
+                            .help("If set the suggested stretch is applied without asking."),
                     )
-                    .arg(Arg::with_name("time").required(true).takes_value(true))
                     .arg(
                         Arg::with_name("encoding")
                             .long("encoding")
                             .short("e")
                             .takes_value(true)
                             .help(
-                                "Needed to parse text-based subtitle formats. Defaults to UTF-8.",
+                                "The encoding of text-based subtitle formats. By default the \
+                                encoding is detected for each file from its BOM or content.",
                             ),
-                    )
-                    .arg(Arg::with_name("fps").long("fps").takes_value(true).help(
-                        "Needed for MicroDVD .sub files. Specifies the FPS that the video \
-                                file is encoded in. Defaults to 25.0",
-                    )),
+                    ),
             )
             .subcommand(SubCommand::with_name("time-mpv").about(
                 "Adjusts the timing of all subs interactively using mpv. `mpv` must be installed.",
@@ -146,12 +862,13 @@ impl GlobalConfig {
                     .settings(&[AppSettings::AllowLeadingHyphen])
                     .about(
                         "Adjusts the timing of all subs that are matched with a video file using \
-                        `alass` (https://github.com/kaegi/alass). This can automatically fix \
-                        wrong timings.",
+                        `alass` (https://github.com/kaegi/alass), or another aligner given by \
+                        --backend or --command. This can automatically fix wrong timings.",
                     )
                     .common_match_args()
+                    .duration_check_args()
                     .arg(Arg::with_name("flags").takes_value(true).help(
-                        "A string of flags that is passed directly to alass for each \
+                        "A string of flags that is passed directly to the aligner for each \
                                 subtitle/video adjustment. The arguments must be quoted so that \
                                 they are interpreted as a single string, for example: \
                                 \n\n  sub-batch alass \"--split-penalty 10\"",
@@ -160,8 +877,81 @@ impl GlobalConfig {
                         Arg::with_name("no_parallel")
                             .long("nopar")
                             .takes_value(false)
+                            .conflicts_with("jobs")
+                            .help(
+                                "If this flag is set sub-batch will not execute alass in parallel. \
+                                Same as --jobs 1.",
+                            ),
+                    )
+                    .arg(
+                        Arg::with_name("suffix")
+                            .long("suffix")
+                            .takes_value(true)
+                            .help(
+                                "Writes the aligned subs next to the originals with this suffix \
+                                before the extension instead of replacing them, e.g. --suffix \
+                                .synced writes ep01.synced.srt. Can be combined with --out-dir.",
+                            ),
+                    )
+                    .arg(
+                        Arg::with_name("jobs")
+                            .long("jobs")
+                            .short("j")
+                            .takes_value(true)
+                            .help(
+                                "How many subs are aligned at the same time. Defaults to the \
+                                number of CPUs.",
+                            ),
+                    )
+                    .arg(
+                        Arg::with_name("force")
+                            .long("force")
+                            .takes_value(false)
+                            .help(
+                                "Aligns all pairs, also those that are unchanged since their last \
+                                successful alignment.",
+                            ),
+                    )
+                    .arg(
+                        Arg::with_name("timeout")
+                            .long("timeout")
+                            .takes_value(true)
+                            .help(
+                                "Stops the aligner if it takes longer than this for a sub, e.g. \
+                                \"10m\". The sub is left unchanged and counted as failed.",
+                            ),
+                    )
+                    .arg(
+                        Arg::with_name("backend")
+                            .long("backend")
+                            .takes_value(true)
+                            .possible_values(&["alass", "ffsubsync"])
+                            .default_value("alass")
+                            .help(
+                                "The program that aligns the subs: alass or ffsubsync \
+                                (https://github.com/smacke/ffsubsync).",
+                            ),
+                    )
+                    .arg(
+                        Arg::with_name("log_dir")
+                            .long("log-dir")
+                            .takes_value(true)
                             .help(
-                                "If this flag is set sub-batch will not execute alass in parallel.",
+                                "The directory that the command line and output of the aligner \
+                                are written to for each sub, as <sub>.log. Defaults to \
+                                .sub-batch-logs in the target directory.",
+                            ),
+                    )
+                    .arg(
+                        Arg::with_name("command")
+                            .long("command")
+                            .takes_value(true)
+                            .conflicts_with("backend")
+                            .help(
+                                "Aligns the subs with this command instead of a --backend. \
+                                {video}, {sub} and {out} are replaced with the paths of the video, \
+                                the subtitle and the file that the aligned subtitle must be \
+                                written to, e.g. \"my-sync {video} {sub} -o {out}\".",
                             ),
                     ),
             )
@@ -192,26 +982,265 @@ impl GlobalConfig {
                 video_area: regex_arg(subcommand_matches, "video_area")?,
                 video_area_scan,
                 secondary_ext_policy: secondary_ext_policy(subcommand_matches),
+                duration_check: duration_check(subcommand_matches)?,
             }),
             "time" => {
-                let mut tc = TimeConfig::timing(timing(subcommand_matches)?);
-                if let Some(encoding) = encoding(subcommand_matches) {
-                    tc.encoding = encoding?;
-                }
-                if let Some(fps) = fps(subcommand_matches) {
-                    tc.fps = fps?;
-                }
+                let given_fps = fps(subcommand_matches).transpose()?;
+                let fps = given_fps.unwrap_or(25.0);
+                let mut tc = TimeConfig::timing(timing(subcommand_matches, fps)?);
+                tc.stretch = stretch(subcommand_matches, fps)?;
+                tc.fps = given_fps;
+                tc.encoding = encoding(subcommand_matches, "encoding")?;
+                tc.output_encoding = encoding(subcommand_matches, "output_encoding")?;
                 CommandConfig::Time(tc)
             }
+            "sync" => {
+                let fps = fps(subcommand_matches).unwrap_or(Ok(25.0))?;
+                CommandConfig::Sync(SyncConfig {
+                    first: sync_anchor(subcommand_matches, "first", fps)?,
+                    second: sync_anchor(subcommand_matches, "second", fps)?,
+                    encoding: encoding(subcommand_matches, "encoding")?,
+                    output_encoding: encoding(subcommand_matches, "output_encoding")?,
+                    fps,
+                })
+            }
+            "convert" => CommandConfig::Convert(ConvertConfig {
+                to: match subcommand_matches.value_of("to").unwrap() {
+                    "srt" => ConvertFormat::Srt,
+                    "ass" => ConvertFormat::Ass,
+                    _ => ConvertFormat::Vtt,
+                },
+                replace: subcommand_matches.is_present("replace"),
+                font: subcommand_matches.value_of("font").unwrap().to_string(),
+                font_size: u32::from_str(subcommand_matches.value_of("font_size").unwrap())
+                    .map_err(|_| anyhow!("invalid --font-size value"))?,
+                encoding: encoding(subcommand_matches, "encoding")?,
+                output_encoding: encoding(subcommand_matches, "output_encoding")?,
+                fps: fps(subcommand_matches).unwrap_or(Ok(25.0))?,
+            }),
+            "clean" => CommandConfig::Clean(CleanConfig {
+                rules: subcommand_matches
+                    .values_of("rules")
+                    .unwrap()
+                    .filter_map(|rule| match rule {
+                        "sdh" => Some(CleanRule::Sdh),
+                        "speakers" => Some(CleanRule::Speakers),
+                        "tags" => Some(CleanRule::Tags),
+                        "music" => Some(CleanRule::Music),
+                        _ => None,
+                    })
+                    .collect(),
+                patterns: subcommand_matches
+                    .values_of("pattern")
+                    .into_iter()
+                    .flatten()
+                    .map(|pattern| {
+                        Regex::new(pattern)
+                            .map_err(|e| anyhow!("invalid --pattern {:?}: {}", pattern, e))
+                    })
+                    .collect::<AnyResult<_>>()?,
+                encoding: encoding(subcommand_matches, "encoding")?,
+                output_encoding: encoding(subcommand_matches, "output_encoding")?,
+                fps: fps(subcommand_matches).unwrap_or(Ok(25.0))?,
+            }),
+            "merge" => {
+                let fps = fps(subcommand_matches).unwrap_or(Ok(25.0))?;
+                CommandConfig::Merge(MergeConfig {
+                    top: subcommand_matches.value_of("top").unwrap().to_string(),
+                    bottom: subcommand_matches.value_of("bottom").unwrap().to_string(),
+                    format: match subcommand_matches.value_of("format").unwrap() {
+                        "srt" => MergeFormat::Srt,
+                        _ => MergeFormat::Ass,
+                    },
+                    tolerance: match time_value(
+                        subcommand_matches.value_of("tolerance").unwrap(),
+                        fps,
+                    )? {
+                        t if t < 0 => bail!("--tolerance can't be negative"),
+                        t => t,
+                    },
+                    name: subcommand_matches.value_of("name").unwrap().to_string(),
+                    font: subcommand_matches.value_of("font").unwrap().to_string(),
+                    font_size: u32::from_str(subcommand_matches.value_of("font_size").unwrap())
+                        .map_err(|_| anyhow!("invalid --font-size value"))?,
+                    top_color: color(subcommand_matches, "top_color")?,
+                    bottom_color: color(subcommand_matches, "bottom_color")?,
+                    encoding: encoding(subcommand_matches, "encoding")?,
+                    output_encoding: encoding(subcommand_matches, "output_encoding")?,
+                    fps,
+                    match_config: MatchFilesConfig {
+                        sub_area: regex_arg(subcommand_matches, "sub_area")?,
+                        sub_area_scan,
+                        video_area: regex_arg(subcommand_matches, "video_area")?,
+                        video_area_scan,
+                        secondary_ext_policy: secondary_ext_policy(subcommand_matches),
+                        duration_check: None,
+                    },
+                })
+            }
+            "info" => CommandConfig::Info(InfoConfig {
+                json: subcommand_matches.is_present("json"),
+                encoding: encoding(subcommand_matches, "encoding")?,
+                fps: fps(subcommand_matches).unwrap_or(Ok(25.0))?,
+            }),
+            "lint" => {
+                let fps = fps(subcommand_matches).unwrap_or(Ok(25.0))?;
+                CommandConfig::Lint(LintConfig {
+                    max_cps: positive_f64(
+                        subcommand_matches.value_of("max_cps").unwrap(),
+                        "max-cps",
+                    )?,
+                    max_line_length: usize::from_str(
+                        subcommand_matches.value_of("max_line_length").unwrap(),
+                    )
+                    .map_err(|_| anyhow!("invalid --max-line-length value"))?,
+                    video_length: subcommand_matches
+                        .value_of("video_length")
+                        .map(|v| time_value(v, fps))
+                        .transpose()?,
+                    encoding: encoding(subcommand_matches, "encoding")?,
+                    fps,
+                })
+            }
+            "fix" => {
+                let fps = fps(subcommand_matches).unwrap_or(Ok(25.0))?;
+                let duration = |name: &str| {
+                    subcommand_matches
+                        .value_of(name)
+                        .map(|v| match time_value(v, fps)? {
+                            t if t <= 0 => bail!("--{} must be positive", name.replace('_', "-")),
+                            t => Ok(t),
+                        })
+                        .transpose()
+                };
+                let min_duration = duration("min_duration")?;
+                let max_duration = duration("max_duration")?;
+                if let (Some(min), Some(max)) = (min_duration, max_duration)
+                    && min > max
+                {
+                    bail!("--min-duration can't be longer than --max-duration");
+                }
+                CommandConfig::Fix(FixConfig {
+                    min_gap: match time_value(subcommand_matches.value_of("min_gap").unwrap(), fps)?
+                    {
+                        t if t < 0 => bail!("--min-gap can't be negative"),
+                        t => t,
+                    },
+                    min_duration,
+                    max_duration,
+                    dry_run: subcommand_matches.is_present("dry_run"),
+                    encoding: encoding(subcommand_matches, "encoding")?,
+                    output_encoding: encoding(subcommand_matches, "output_encoding")?,
+                    fps,
+                })
+            }
+            "extract" => CommandConfig::Extract(ExtractConfig {
+                list: subcommand_matches.is_present("list"),
+                languages: subcommand_matches
+                    .values_of("lang")
+                    .into_iter()
+                    .flatten()
+                    .map(str::to_string)
+                    .collect(),
+                tracks: subcommand_matches
+                    .values_of("track")
+                    .into_iter()
+                    .flatten()
+                    .map(|track| {
+                        u64::from_str(track)
+                            .map_err(|_| anyhow!("invalid --track value {:?}", track))
+                    })
+                    .collect::<AnyResult<_>>()?,
+            }),
+            "ref-sync" => {
+                let fps = fps(subcommand_matches).unwrap_or(Ok(25.0))?;
+                CommandConfig::RefSync(RefSyncConfig {
+                    reference: match (
+                        subcommand_matches.value_of("ref_ext"),
+                        subcommand_matches.value_of("ref_track"),
+                    ) {
+                        (Some(ext), _) => {
+                            Reference::Extension(ext.trim_start_matches('.').to_string())
+                        }
+                        (_, Some(track)) => match u64::from_str(track) {
+                            Ok(number) => Reference::TrackNumber(number),
+                            Err(_) => Reference::TrackLanguage(track.to_string()),
+                        },
+                        _ => unreachable!(),
+                    },
+                    max_offset: match time_value(
+                        subcommand_matches.value_of("max_offset").unwrap(),
+                        fps,
+                    )? {
+                        t if t < 0 => bail!("--max-offset can't be negative"),
+                        t => t,
+                    },
+                    split_penalty: if subcommand_matches.is_present("no_split") {
+                        None
+                    } else {
+                        match time_value(
+                            subcommand_matches.value_of("split_penalty").unwrap(),
+                            fps,
+                        )? {
+                            t if t < 0 => bail!("--split-penalty can't be negative"),
+                            t => Some(t),
+                        }
+                    },
+                    encoding: encoding(subcommand_matches, "encoding")?,
+                    output_encoding: encoding(subcommand_matches, "output_encoding")?,
+                    fps,
+                    match_config: MatchFilesConfig {
+                        sub_area: regex_arg(subcommand_matches, "sub_area")?,
+                        sub_area_scan,
+                        video_area: regex_arg(subcommand_matches, "video_area")?,
+                        video_area_scan,
+                        secondary_ext_policy: secondary_ext_policy(subcommand_matches),
+                        duration_check: None,
+                    },
+                })
+            }
+            "diagnose" => CommandConfig::Diagnose(DiagnoseConfig {
+                tolerance: positive_f64(
+                    subcommand_matches.value_of("tolerance").unwrap(),
+                    "tolerance",
+                )? / 100.0,
+                apply: subcommand_matches.is_present("apply"),
+                encoding: encoding(subcommand_matches, "encoding")?,
+                match_config: MatchFilesConfig {
+                    sub_area: regex_arg(subcommand_matches, "sub_area")?,
+                    sub_area_scan,
+                    video_area: regex_arg(subcommand_matches, "video_area")?,
+                    video_area_scan,
+                    secondary_ext_policy: secondary_ext_policy(subcommand_matches),
+                    duration_check: None,
+                },
+            }),
             "alass" => CommandConfig::Alass(AlassConfig {
                 flags: alass_flags(subcommand_matches),
-                no_parallel: subcommand_matches.is_present("no_parallel"),
+                backend: align_backend(subcommand_matches)?,
+                log_dir: subcommand_matches.value_of("log_dir").map(PathBuf::from),
+                suffix: match subcommand_matches.value_of("suffix") {
+                    Some(suffix) if suffix.is_empty() || suffix.contains(['/', '\\']) => {
+                        bail!("invalid --suffix value: {:?}", suffix)
+                    }
+                    suffix => suffix.map(String::from),
+                },
+                jobs: jobs(subcommand_matches)?,
+                force: subcommand_matches.is_present("force"),
+                timeout: subcommand_matches
+                    .value_of("timeout")
+                    .map(|value| match time_value(value, 25.0)? {
+                        t if t <= 0 => bail!("--timeout must be positive"),
+                        t => Ok(t),
+                    })
+                    .transpose()?,
                 match_config: MatchFilesConfig {
                     sub_area: regex_arg(subcommand_matches, "sub_area")?,
                     sub_area_scan,
                     video_area: regex_arg(subcommand_matches, "video_area")?,
                     video_area_scan,
                     secondary_ext_policy: secondary_ext_policy(subcommand_matches),
+                    duration_check: duration_check(subcommand_matches)?,
                 },
             }),
             "time-mpv" => CommandConfig::Mpv,
@@ -223,6 +1252,12 @@ impl GlobalConfig {
                 path: matches.value_of("path").unwrap().into(),
                 confirm: !matches.is_present("no_confirm"),
                 color: !matches.is_present("no_color"),
+                strict: matches.is_present("strict"),
+                verbose: matches.is_present("verbose"),
+                backup: matches
+                    .is_present("backup")
+                    .then(|| matches.value_of("backup").unwrap_or(".bak").to_string()),
+                out_dir: matches.value_of("out_dir").map(PathBuf::from),
                 sub_filter: regex_arg(&matches, "filter_sub")?,
                 video_filter: regex_arg(&matches, "filter_video")?,
             },
@@ -243,6 +1278,7 @@ fn secondary_ext_policy(matches: &ArgMatches) -> SecondaryExtensionPolicy {
 
 trait CommonMatchArgs {
     fn common_match_args(self) -> Self;
+    fn duration_check_args(self) -> Self;
 }
 
 impl<'a, 'b> CommonMatchArgs for App<'a, 'b> {
@@ -322,6 +1358,81 @@ impl<'a, 'b> CommonMatchArgs for App<'a, 'b> {
                 ),
         )
     }
+
+    fn duration_check_args(self) -> Self {
+        self.arg(
+            Arg::with_name("duration_tolerance")
+                .long("duration-tolerance")
+                .takes_value(true)
+                .default_value("10m")
+                .help(
+                    "Pairs whose last cue ends more than this time before or after the end of \
+                    the video are flagged as a likely mismatch, and refused with --strict. The \
+                    duration is read from the headers of Matroska and MP4 files.",
+                ),
+        )
+        .arg(
+            Arg::with_name("encoding")
+                .long("encoding")
+                .short("e")
+                .takes_value(true)
+                .help(
+                    "The encoding that text-based subtitles are read with to check their \
+                    duration. By default the encoding is detected for each file from its BOM or \
+                    content.",
+                ),
+        )
+    }
+}
+
+trait EncodingAndFpsArgs {
+    fn encoding_and_fps_args(self) -> Self;
+    fn output_encoding_arg(self) -> Self;
+}
+
+impl<'a, 'b> EncodingAndFpsArgs for App<'a, 'b> {
+    fn encoding_and_fps_args(self) -> Self {
+        self.arg(
+            Arg::with_name("encoding")
+                .long("encoding")
+                .short("e")
+                .takes_value(true)
+                .help(
+                    "The encoding of text-based subtitle formats. By default the encoding is \
+                    detected for each file from its BOM or content.",
+                ),
+        )
+        .arg(Arg::with_name("fps").long("fps").takes_value(true).help(
+            "Needed for MicroDVD .sub files. Specifies the FPS that the video \
+                    file is encoded in. Defaults to 25.0 (for time: to the frame rate of \
+                    the matched Matroska or MP4 video, if there is one).",
+        ))
+    }
+
+    fn output_encoding_arg(self) -> Self {
+        self.arg(
+            Arg::with_name("output_encoding")
+                .long("output-encoding")
+                .takes_value(true)
+                .help(
+                    "Re-encodes the written subtitles with this encoding (e.g. UTF-8). By \
+                    default subtitles are written with the encoding they were read with.",
+                ),
+        )
+    }
+}
+
+fn sync_anchor_arg(name: &str) -> Arg<'_, '_> {
+    Arg::with_name(name)
+        .long(name)
+        .takes_value(true)
+        .number_of_values(2)
+        .value_names(&["CUE", "TIME"])
+        .required(true)
+        .help(
+            "A cue and the time it should start at, e.g. `3 00:01:02.300`. The cue is either \
+            the (1-based) index of the cue in the file or a text that the cue contains.",
+        )
 }
 
 fn check_args<'a>(matches: &'a ArgMatches) -> (&'a str, &'a ArgMatches<'a>) {
@@ -334,6 +1445,33 @@ fn check_args<'a>(matches: &'a ArgMatches) -> (&'a str, &'a ArgMatches<'a>) {
     }
 }
 
+fn duration_check(matches: &ArgMatches) -> AnyResult<Option<DurationCheck>> {
+    let Some(value) = matches.value_of("duration_tolerance") else {
+        return Ok(None);
+    };
+    let tolerance = match time_value(value, 25.0)? {
+        t if t < 0 => bail!("--duration-tolerance can't be negative"),
+        t => t,
+    };
+    Ok(Some(DurationCheck {
+        tolerance,
+        encoding: encoding(matches, "encoding")?,
+    }))
+}
+
+fn jobs(matches: &ArgMatches) -> AnyResult<Option<usize>> {
+    if matches.is_present("no_parallel") {
+        return Ok(Some(1));
+    }
+    matches
+        .value_of("jobs")
+        .map(|value| match usize::from_str(value) {
+            Ok(jobs) if jobs > 0 => Ok(jobs),
+            _ => bail!("invalid --jobs value: {}", value),
+        })
+        .transpose()
+}
+
 fn regex_arg(matches: &ArgMatches, key: &str) -> AnyResult<Option<Regex>> {
     Ok(if let Some(v) = matches.value_of(key) {
         Some(Regex::new(v)?)
@@ -342,21 +1480,228 @@ fn regex_arg(matches: &ArgMatches, key: &str) -> AnyResult<Option<Regex>> {
     })
 }
 
-fn timing(matches: &ArgMatches) -> Result<i64, ParseIntError> {
-    let v = matches.value_of("time").unwrap();
-    i64::from_str(v)
+fn timing(matches: &ArgMatches, fps: f64) -> AnyResult<i64> {
+    match matches.value_of("time") {
+        Some(v) => time_value(v, fps),
+        None => Ok(0),
+    }
+}
+
+fn stretch(matches: &ArgMatches, fps: f64) -> AnyResult<Option<StretchConfig>> {
+    let anchor = match matches.value_of("anchor") {
+        Some(v) => time_value(v, fps)?,
+        None => 0,
+    };
+
+    if let Some(factor) = matches.value_of("stretch") {
+        let factor = positive_f64(factor, "stretch")?;
+        return Ok(Some(StretchConfig {
+            factor,
+            anchor,
+            fps_conversion: None,
+        }));
+    }
+
+    if let (Some(from), Some(to)) = (matches.value_of("fps_from"), matches.value_of("fps_to")) {
+        let from = positive_f64(from, "fps-from")?;
+        let to = positive_f64(to, "fps-to")?;
+        return Ok(Some(StretchConfig {
+            factor: from / to,
+            anchor,
+            fps_conversion: Some((from, to)),
+        }));
+    }
+
+    Ok(None)
+}
+
+fn positive_f64(value: &str, name: &str) -> AnyResult<f64> {
+    match f64::from_str(value) {
+        Ok(v) if v > 0.0 && v.is_finite() => Ok(v),
+        _ => bail!("invalid --{} value: {}", name, value),
+    }
+}
+
+fn sync_anchor(matches: &ArgMatches, name: &str, fps: f64) -> AnyResult<SyncAnchor> {
+    let mut values = matches.values_of(name).unwrap();
+    let (cue, time) = (values.next().unwrap(), values.next().unwrap());
+
+    let cue = match usize::from_str(cue) {
+        Ok(0) => bail!("invalid --{} cue index: cue indexes start at 1", name),
+        Ok(index) => CueSelector::Index(index),
+        Err(_) => CueSelector::Text(cue.to_string()),
+    };
+
+    Ok(SyncAnchor {
+        cue,
+        time: time_value(time, fps)?,
+    })
+}
+
+/// Parses a time value to milliseconds. The value can be given as:
+///
+/// * a number of milliseconds (`1500`)
+/// * numbers with units (`1m2.5s`, `300ms`, `1h`) where `f` means frames at `fps` (`12f`)
+/// * a SRT/ASS style timestamp (`00:00:01,200`, `0:00:01.20`, `01:02.3`)
+///
+/// Any of these can be prefixed with `-` to give a negative time.
+fn time_value(value: &str, fps: f64) -> AnyResult<i64> {
+    let (negative, rest) = match value.trim().strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (
+            false,
+            value.trim().strip_prefix('+').unwrap_or(value.trim()),
+        ),
+    };
+
+    if rest.is_empty() {
+        bail!("invalid time \"{}\": missing value", value);
+    }
+
+    let msecs = if rest.contains(':') {
+        timestamp_value(value, rest)?
+    } else if rest.chars().all(|c| c.is_ascii_digit()) {
+        i64::from_str(rest).map_err(|_| anyhow!("invalid time \"{}\": value too large", value))?
+    } else {
+        unit_value(value, rest, fps)?
+    };
+
+    Ok(if negative { -msecs } else { msecs })
+}
+
+fn timestamp_value(value: &str, timestamp: &str) -> AnyResult<i64> {
+    let (clock, fraction) = match timestamp.find(['.', ',']) {
+        Some(i) => (&timestamp[..i], &timestamp[i + 1..]),
+        None => (timestamp, ""),
+    };
+
+    let parts = clock.split(':').collect::<Vec<_>>();
+    if parts.len() > 3 {
+        bail!(
+            "invalid time \"{}\": a timestamp has at most three parts (hours:minutes:seconds)",
+            value
+        );
+    }
+
+    let mut msecs: i64 = 0;
+    for part in parts {
+        let n = u32::from_str(part)
+            .map_err(|_| anyhow!("invalid time \"{}\": could not parse \"{}\"", value, part))?;
+        msecs = msecs * 60 + n as i64;
+    }
+    msecs *= 1000;
+
+    if !fraction.is_empty() {
+        if !fraction.chars().all(|c| c.is_ascii_digit()) {
+            bail!(
+                "invalid time \"{}\": could not parse fraction \"{}\"",
+                value,
+                fraction
+            );
+        }
+        let digits = format!("{:0<3}", &fraction[..fraction.len().min(3)]);
+        msecs += i64::from_str(&digits).unwrap();
+    }
+    Ok(msecs)
 }
 
-fn encoding(matches: &ArgMatches) -> Option<AnyResult<&'static Encoding>> {
+fn unit_value(value: &str, mut rest: &str, fps: f64) -> AnyResult<i64> {
+    let mut msecs = 0.0;
+
+    while !rest.is_empty() {
+        let number_end = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let (number, after) = rest.split_at(number_end);
+
+        let unit_end = after
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(after.len());
+        let (unit, after) = after.split_at(unit_end);
+
+        let part = &rest[..number_end + unit_end];
+        let n = f64::from_str(number).map_err(|_| {
+            anyhow!(
+                "invalid time \"{}\": could not parse \"{}\" (invalid number)",
+                value,
+                part
+            )
+        })?;
+
+        msecs += n
+            * match unit {
+                "ms" => 1.0,
+                "s" => 1000.0,
+                "m" => 60_000.0,
+                "h" => 3_600_000.0,
+                "f" => 1000.0 / fps,
+                "" => bail!(
+                    "invalid time \"{}\": could not parse \"{}\" (missing unit)",
+                    value,
+                    part
+                ),
+                _ => bail!(
+                    "invalid time \"{}\": could not parse \"{}\" (unknown unit \"{}\", use ms, s, m, h or f)",
+                    value,
+                    part,
+                    unit
+                ),
+            };
+
+        rest = after;
+    }
+
+    Ok(msecs.round() as i64)
+}
+
+fn encoding(matches: &ArgMatches, name: &str) -> AnyResult<Option<&'static Encoding>> {
     matches
-        .value_of("encoding")
-        .map(|v| Encoding::for_label(v.as_bytes()).ok_or_else(|| anyhow!("invalid encoding")))
+        .value_of(name)
+        .map(|v| {
+            Encoding::for_label(v.as_bytes()).ok_or_else(|| anyhow!("invalid encoding {:?}", v))
+        })
+        .transpose()
+}
+
+fn color(matches: &ArgMatches, name: &str) -> AnyResult<u32> {
+    let value = matches.value_of(name).unwrap();
+    let hex = value.trim_start_matches('#');
+    match u32::from_str_radix(hex, 16) {
+        Ok(color) if hex.len() == 6 => Ok(color),
+        _ => bail!(
+            "invalid --{} value {:?} (expected a RGB hex color like FFFF80)",
+            name.replace('_', "-"),
+            value
+        ),
+    }
 }
 
 fn fps(matches: &ArgMatches) -> Option<Result<f64, ParseFloatError>> {
     matches.value_of("fps").map(f64::from_str)
 }
 
+fn align_backend(matches: &ArgMatches) -> AnyResult<AlignBackend> {
+    if let Some(command) = matches.value_of("command") {
+        let template = command
+            .split_ascii_whitespace()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        if template.is_empty() {
+            bail!("--command can't be empty");
+        }
+        for placeholder in ["{sub}", "{out}"] {
+            if !template[1..].iter().any(|arg| arg.contains(placeholder)) {
+                bail!("--command must contain {}", placeholder);
+            }
+        }
+        return Ok(AlignBackend::Template(template));
+    }
+    Ok(match matches.value_of("backend").unwrap() {
+        "ffsubsync" => AlignBackend::Ffsubsync,
+        _ => AlignBackend::Alass,
+    })
+}
+
 fn alass_flags(matches: &ArgMatches) -> Vec<String> {
     match matches.value_of("flags") {
         None => Vec::new(),
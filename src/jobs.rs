@@ -0,0 +1,149 @@
+//! Runs external programs so that they can be stopped: each run can have a timeout, and all
+//! running programs are killed when sub-batch is interrupted.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often a running program is checked for having exited or timed out.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+static RUNNING: Mutex<BTreeMap<u64, Job>> = Mutex::new(BTreeMap::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+struct Job {
+    child: Child,
+    /// The temporary file that the program writes to, which is removed if it is killed.
+    temp: PathBuf,
+}
+
+/// A program that ran to the end or was killed because of its timeout.
+pub struct Finished {
+    pub output: Output,
+    pub timed_out: bool,
+}
+
+/// Runs the command with its output collected. The program is killed if it runs longer than
+/// `timeout`. Fails with `ErrorKind::Interrupted` if sub-batch is interrupted, in which case the
+/// program is killed and `temp` is removed.
+pub fn run(cmd: &mut Command, timeout: Option<Duration>, temp: &Path) -> io::Result<Finished> {
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    // The program gets a process group of its own so that the programs it starts (e.g. ffmpeg)
+    // can be killed with it.
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(cmd, 0);
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let (stdout, stderr) = {
+        let mut running = lock();
+        if CANCELLED.load(Ordering::SeqCst) {
+            return Err(interrupted());
+        }
+        let mut child = cmd.spawn()?;
+        let pipes = (child.stdout.take(), child.stderr.take());
+        running.insert(
+            id,
+            Job {
+                child,
+                temp: temp.to_path_buf(),
+            },
+        );
+        pipes
+    };
+    // The pipes are read while the program runs so that it doesn't block on a full pipe.
+    let stdout = thread::spawn(move || read_all(stdout));
+    let stderr = thread::spawn(move || read_all(stderr));
+
+    let start = Instant::now();
+    let mut timed_out = false;
+    let status = loop {
+        {
+            let mut running = lock();
+            // The job is gone if it was killed by `cancel_all`.
+            let Some(job) = running.get_mut(&id) else {
+                return Err(interrupted());
+            };
+            let status = match job.child.try_wait() {
+                Ok(Some(status)) => Some(status),
+                Ok(None) if timeout.is_some_and(|timeout| start.elapsed() >= timeout) => {
+                    timed_out = true;
+                    kill(&mut job.child);
+                    Some(job.child.wait()?)
+                }
+                Ok(None) => None,
+                Err(e) => {
+                    kill(&mut job.child);
+                    running.remove(&id);
+                    return Err(e);
+                }
+            };
+            if let Some(status) = status {
+                running.remove(&id);
+                break status;
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    Ok(Finished {
+        output: Output {
+            status,
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        },
+        timed_out,
+    })
+}
+
+/// Kills all running programs and removes their temporary files. Programs can't be started
+/// afterwards.
+pub fn cancel_all() {
+    CANCELLED.store(true, Ordering::SeqCst);
+    let mut running = lock();
+    for (_, mut job) in std::mem::take(&mut *running) {
+        kill(&mut job.child);
+        let _ = job.child.wait();
+        let _ = fs::remove_file(&job.temp);
+    }
+}
+
+#[cfg(unix)]
+fn kill(child: &mut Child) {
+    // SAFETY: kill has no memory safety requirements. The child hasn't been waited for, so its
+    // process group still exists.
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    let _ = child.kill();
+}
+
+#[cfg(not(unix))]
+fn kill(child: &mut Child) {
+    let _ = child.kill();
+}
+
+fn lock() -> std::sync::MutexGuard<'static, BTreeMap<u64, Job>> {
+    // A panicking job can't leave the map in an inconsistent state.
+    RUNNING.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn read_all(pipe: Option<impl Read>) -> Vec<u8> {
+    let mut buf = Vec::new();
+    if let Some(mut pipe) = pipe {
+        let _ = pipe.read_to_end(&mut buf);
+    }
+    buf
+}
+
+fn interrupted() -> io::Error {
+    io::Error::new(ErrorKind::Interrupted, "interrupted")
+}
@@ -1,16 +1,45 @@
+mod align;
+mod aligner;
+mod cache;
 mod config;
+mod jobs;
+mod mkv;
+mod probe;
 mod scanner;
+mod subtitle;
 mod commands {
     pub mod alass;
+    pub mod clean;
+    pub mod convert;
+    pub mod diagnose;
+    pub mod extract;
+    pub mod fix;
+    pub mod info;
+    pub mod lint;
+    pub mod merge;
     pub mod mpv;
+    pub mod ref_sync;
     pub mod rename;
+    pub mod sync;
     pub mod time;
-    mod util;
+    pub mod util;
 }
+use crate::commands::clean::CleanCommand;
+use crate::commands::convert::ConvertCommand;
+use crate::commands::diagnose::DiagnoseCommand;
+use crate::commands::extract::ExtractCommand;
+use crate::commands::fix::FixCommand;
+use crate::commands::info::InfoCommand;
+use crate::commands::lint::LintCommand;
+use crate::commands::merge::MergeCommand;
 use crate::commands::mpv::MpvCommand;
+use crate::commands::ref_sync::RefSyncCommand;
 use crate::commands::rename::RenameCommand;
+use crate::commands::sync::SyncCommand;
 use crate::commands::time::TimeCommand;
+use crate::commands::util::PartialSuccess;
 use crate::config::{CommandConfig, GlobalConfig};
+use crate::subtitle::atomic;
 use CommandConfig::*;
 use alass::AlassCommand;
 use anyhow::Result as AnyResult;
@@ -27,6 +56,10 @@ fn main() {
     let catch = std::panic::catch_unwind(|| {
         if let Err(e) = run() {
             restore_terminal();
+            if let Some(partial) = e.downcast_ref::<PartialSuccess>() {
+                eprintln!("\nwarning: {}", partial);
+                process::exit(2);
+            }
             eprintln!("\nerror: {}", e);
             process::exit(1);
         }
@@ -40,6 +73,10 @@ fn main() {
 
 fn setup_signal_handler() {
     let handler = ctrlc::set_handler(|| {
+        // Running aligners are killed before their output can replace a subtitle.
+        jobs::cancel_all();
+        // The process exits without dropping the temporary files of atomic writes.
+        atomic::remove_temp_files();
         restore_terminal();
         process::exit(1);
     });
@@ -56,6 +93,16 @@ fn run() -> AnyResult<()> {
     match cmd_config {
         Rename(c) => RenameCommand::new(&global_config, c).run(),
         Time(c) => TimeCommand::new(&global_config, c).run(),
+        Sync(c) => SyncCommand::new(&global_config, c).run(),
+        Convert(c) => ConvertCommand::new(&global_config, c).run(),
+        Clean(c) => CleanCommand::new(&global_config, c).run(),
+        Merge(c) => MergeCommand::new(&global_config, c).run(),
+        Info(c) => InfoCommand::new(&global_config, c).run(),
+        Lint(c) => LintCommand::new(&global_config, c).run(),
+        Fix(c) => FixCommand::new(&global_config, c).run(),
+        Extract(c) => ExtractCommand::new(&global_config, c).run(),
+        Diagnose(c) => DiagnoseCommand::new(&global_config, c).run(),
+        RefSync(c) => RefSyncCommand::new(&global_config, c).run(),
         Alass(c) => AlassCommand::new(&global_config, c).run(),
         Mpv => MpvCommand::new(&global_config).run(),
     }?;
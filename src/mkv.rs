@@ -0,0 +1,472 @@
+//! A minimal Matroska (EBML) reader that reads the tracks of a file and the blocks of its
+//! subtitle tracks.
+//!
+//! The elements are read in file order, without building a tree: the master elements that we
+//! need (segment, info, tracks, clusters...) are entered and everything else is skipped. This
+//! also works for elements with an unknown size, which are common for clusters in files that
+//! were written as a stream.
+
+use anyhow::Result as AnyResult;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufReader, Cursor, ErrorKind, Read, Seek};
+use std::path::Path;
+
+/// File extensions of Matroska files that can contain subtitle tracks.
+pub const EXTENSIONS: &[&str] = &["mkv", "mk3d", "webm"];
+
+const EBML: u32 = 0x1A45DFA3;
+const DOC_TYPE: u32 = 0x4282;
+const SEGMENT: u32 = 0x18538067;
+const INFO: u32 = 0x1549A966;
+const TIMESTAMP_SCALE: u32 = 0x2AD7B1;
+const DURATION: u32 = 0x4489;
+const TRACKS: u32 = 0x1654AE6B;
+const TRACK_ENTRY: u32 = 0xAE;
+const TRACK_NUMBER: u32 = 0xD7;
+const TRACK_TYPE: u32 = 0x83;
+const CODEC_ID: u32 = 0x86;
+const CODEC_PRIVATE: u32 = 0x63A2;
+const LANGUAGE: u32 = 0x22B59C;
+const LANGUAGE_IETF: u32 = 0x22B59D;
+const NAME: u32 = 0x536E;
+const FLAG_DEFAULT: u32 = 0x88;
+const FLAG_FORCED: u32 = 0x55AA;
+const DEFAULT_DURATION: u32 = 0x23E383;
+const CONTENT_ENCODINGS: u32 = 0x6D80;
+const CONTENT_ENCODING: u32 = 0x6240;
+const CONTENT_COMPRESSION: u32 = 0x5034;
+const CONTENT_COMP_ALGO: u32 = 0x4254;
+const CONTENT_COMP_SETTINGS: u32 = 0x4255;
+const CONTENT_ENCRYPTION: u32 = 0x5035;
+const CLUSTER: u32 = 0x1F43B675;
+const CLUSTER_TIMESTAMP: u32 = 0xE7;
+const SIMPLE_BLOCK: u32 = 0xA3;
+const BLOCK_GROUP: u32 = 0xA0;
+const BLOCK: u32 = 0xA1;
+const BLOCK_DURATION: u32 = 0x9B;
+
+/// The master elements whose children are read.
+const ENTERED: &[u32] = &[
+    SEGMENT,
+    INFO,
+    TRACKS,
+    TRACK_ENTRY,
+    CONTENT_ENCODINGS,
+    CONTENT_ENCODING,
+    CONTENT_COMPRESSION,
+    CLUSTER,
+    BLOCK_GROUP,
+];
+
+/// Elements that are larger than this (apart from blocks that are skipped) are treated as a
+/// broken file rather than read into memory.
+const MAX_ELEMENT_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Track types.
+pub const VIDEO_TRACK: u64 = 0x1;
+pub const AUDIO_TRACK: u64 = 0x2;
+pub const SUBTITLE_TRACK: u64 = 0x11;
+
+#[derive(Debug, Default)]
+pub struct MkvInfo {
+    /// The duration of the file in milliseconds.
+    pub duration: Option<i64>,
+    pub tracks: Vec<Track>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Track {
+    pub number: u64,
+    pub track_type: u64,
+    /// The Matroska codec ID, e.g. S_TEXT/UTF8.
+    pub codec: String,
+    pub codec_private: Vec<u8>,
+    /// The ISO 639-2 language.
+    pub language: String,
+    /// The IETF (BCP 47) language, which is used instead of `language` if it is set.
+    pub language_ietf: Option<String>,
+    pub name: Option<String>,
+    pub default: bool,
+    pub forced: bool,
+    /// The duration of a frame in nanoseconds, if all frames have the same duration.
+    pub default_duration: Option<u64>,
+    compression: Option<Compression>,
+    encrypted: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Compression {
+    Zlib,
+    /// The bytes that were removed from the start of every frame.
+    HeaderStripping(Vec<u8>),
+    Other(u64),
+}
+
+/// A frame of a track. Times are in milliseconds.
+#[derive(Debug)]
+pub struct Block {
+    pub track: u64,
+    pub start: i64,
+    pub duration: Option<i64>,
+    pub data: Vec<u8>,
+}
+
+impl Track {
+    pub fn is_subtitle(&self) -> bool {
+        self.track_type == SUBTITLE_TRACK
+    }
+
+    pub fn language(&self) -> &str {
+        self.language_ietf.as_deref().unwrap_or(&self.language)
+    }
+
+    fn new() -> Self {
+        Track {
+            number: 0,
+            track_type: 0,
+            codec: String::new(),
+            codec_private: Vec::new(),
+            // The default of the Language element.
+            language: "eng".to_string(),
+            language_ietf: None,
+            name: None,
+            default: true,
+            forced: false,
+            default_duration: None,
+            compression: None,
+            encrypted: false,
+        }
+    }
+
+    fn decode(&self, data: Vec<u8>) -> AnyResult<Vec<u8>> {
+        if self.encrypted {
+            bail!("track {} is encrypted", self.number);
+        }
+        match &self.compression {
+            None => Ok(data),
+            Some(Compression::HeaderStripping(header)) => Ok([header.as_slice(), &data].concat()),
+            Some(Compression::Zlib) => {
+                miniz_oxide::inflate::decompress_to_vec_zlib(&data).map_err(|e| {
+                    anyhow!(
+                        "failed to decompress a block of track {}: {}",
+                        self.number,
+                        e
+                    )
+                })
+            }
+            Some(Compression::Other(algo)) => bail!(
+                "track {} uses an unsupported compression ({})",
+                self.number,
+                algo
+            ),
+        }
+    }
+}
+
+/// Reads the info and tracks of a Matroska file.
+pub fn read_info(path: &Path) -> AnyResult<MkvInfo> {
+    let (info, _) = read(path, None)?;
+    Ok(info)
+}
+
+/// Reads the info, tracks and the blocks of the given tracks of a Matroska file. The blocks are
+/// in file order.
+pub fn read_blocks(path: &Path, tracks: &[u64]) -> AnyResult<(MkvInfo, Vec<Block>)> {
+    read(path, Some(&tracks.iter().copied().collect()))
+}
+
+/// A block with its track number, start and duration in ticks and frame.
+type RawBlock = (u64, i64, Option<i64>, Vec<u8>);
+
+/// The block in the block group that is being read and its duration (in ticks), which can come
+/// before or after the block.
+#[derive(Default)]
+struct PendingBlock {
+    block: Option<(u64, i64, Vec<u8>)>,
+    duration: Option<i64>,
+}
+
+impl PendingBlock {
+    fn flush(pending: &mut Option<PendingBlock>, blocks: &mut Vec<RawBlock>) {
+        if let Some(PendingBlock {
+            block: Some((track, start, data)),
+            duration,
+        }) = pending.take()
+        {
+            blocks.push((track, start, duration, data));
+        }
+    }
+}
+
+fn read(path: &Path, wanted: Option<&HashSet<u64>>) -> AnyResult<(MkvInfo, Vec<Block>)> {
+    let mut reader = EbmlReader::new(File::open(path)?);
+
+    match reader.element_header()? {
+        Some((EBML, Some(size))) => {
+            let doc_type = reader.child_string(size, DOC_TYPE)?;
+            if !matches!(doc_type.as_deref(), Some("matroska" | "webm")) {
+                bail!("not a Matroska file (doc type {:?})", doc_type);
+            }
+        }
+        _ => bail!("not a Matroska file"),
+    }
+
+    let mut info = MkvInfo::default();
+    let mut timestamp_scale = 1_000_000;
+    let mut duration = None;
+    let mut cluster_timestamp = 0;
+    let mut pending: Option<PendingBlock> = None;
+    let mut blocks = Vec::new();
+
+    while let Some((id, size)) = reader.element_header()? {
+        if ENTERED.contains(&id) {
+            match id {
+                TRACK_ENTRY => info.tracks.push(Track::new()),
+                CLUSTER => {
+                    PendingBlock::flush(&mut pending, &mut blocks);
+                    // The tracks come before the clusters in almost all files.
+                    if wanted.is_none() && !info.tracks.is_empty() {
+                        break;
+                    }
+                }
+                BLOCK_GROUP => {
+                    PendingBlock::flush(&mut pending, &mut blocks);
+                    pending = Some(PendingBlock::default());
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        let size = size.ok_or_else(|| anyhow!("element {:#X} has an unknown size", id))?;
+        let track = info.tracks.last_mut();
+        match (id, track) {
+            (TIMESTAMP_SCALE, _) => timestamp_scale = reader.uint(size)?,
+            (DURATION, _) => duration = Some(reader.float(size)?),
+            (TRACK_NUMBER, Some(track)) => track.number = reader.uint(size)?,
+            (TRACK_TYPE, Some(track)) => track.track_type = reader.uint(size)?,
+            (CODEC_ID, Some(track)) => track.codec = reader.string(size)?,
+            (CODEC_PRIVATE, Some(track)) => track.codec_private = reader.bytes(size)?,
+            (LANGUAGE, Some(track)) => track.language = reader.string(size)?,
+            (LANGUAGE_IETF, Some(track)) => track.language_ietf = Some(reader.string(size)?),
+            (NAME, Some(track)) => track.name = Some(reader.string(size)?),
+            (FLAG_DEFAULT, Some(track)) => track.default = reader.uint(size)? != 0,
+            (FLAG_FORCED, Some(track)) => track.forced = reader.uint(size)? != 0,
+            (DEFAULT_DURATION, Some(track)) => track.default_duration = Some(reader.uint(size)?),
+            (CONTENT_COMP_ALGO, Some(track)) => {
+                track.compression = Some(match reader.uint(size)? {
+                    0 => Compression::Zlib,
+                    3 => Compression::HeaderStripping(Vec::new()),
+                    algo => Compression::Other(algo),
+                })
+            }
+            (CONTENT_COMP_SETTINGS, Some(track)) => {
+                let settings = reader.bytes(size)?;
+                if let Some(Compression::HeaderStripping(header)) = &mut track.compression {
+                    *header = settings;
+                }
+            }
+            (CONTENT_ENCRYPTION, Some(track)) => {
+                track.encrypted = true;
+                reader.skip(size)?;
+            }
+            (CLUSTER_TIMESTAMP, _) => cluster_timestamp = reader.uint(size)? as i64,
+            (SIMPLE_BLOCK, _) => {
+                PendingBlock::flush(&mut pending, &mut blocks);
+                if let Some((track, timestamp, data)) = reader.block(size, wanted)? {
+                    blocks.push((track, cluster_timestamp + timestamp, None, data));
+                }
+            }
+            (BLOCK, _) => {
+                let block = reader.block(size, wanted)?;
+                if let (Some(pending), Some((track, timestamp, data))) = (&mut pending, block) {
+                    pending.block = Some((track, cluster_timestamp + timestamp, data));
+                }
+            }
+            (BLOCK_DURATION, _) => {
+                let ticks = reader.uint(size)? as i64;
+                if let Some(pending) = &mut pending {
+                    pending.duration = Some(ticks);
+                }
+            }
+            _ => reader.skip(size)?,
+        }
+    }
+    PendingBlock::flush(&mut pending, &mut blocks);
+
+    let msecs = |ticks: i64| (ticks as i128 * timestamp_scale as i128 / 1_000_000) as i64;
+    info.duration = duration.map(|ticks| (ticks * timestamp_scale as f64 / 1_000_000.0) as i64);
+
+    let blocks = blocks
+        .into_iter()
+        .map(|(number, start, duration, data)| {
+            let track = info.tracks.iter().find(|track| track.number == number);
+            Ok(Block {
+                track: number,
+                start: msecs(start),
+                duration: duration.map(msecs),
+                data: match track {
+                    Some(track) => track.decode(data)?,
+                    None => data,
+                },
+            })
+        })
+        .collect::<AnyResult<_>>()?;
+    Ok((info, blocks))
+}
+
+struct EbmlReader<R> {
+    reader: BufReader<R>,
+}
+
+impl<R: Read + Seek> EbmlReader<R> {
+    fn new(reader: R) -> Self {
+        EbmlReader {
+            reader: BufReader::new(reader),
+        }
+    }
+
+    /// Reads the ID and size of the next element. The size is `None` if it is unknown. Returns
+    /// `None` at the end of the file.
+    fn element_header(&mut self) -> AnyResult<Option<(u32, Option<u64>)>> {
+        let mut first = [0];
+        match self.reader.read_exact(&mut first) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let length = first[0].leading_zeros() as usize + 1;
+        if length > 4 {
+            bail!("invalid element ID");
+        }
+        let mut id = first[0] as u32;
+        for _ in 1..length {
+            id = id << 8 | self.byte()? as u32;
+        }
+        let (size, _) = self.vint()?;
+        Ok(Some((id, size)))
+    }
+
+    /// Reads a variable size integer without its length marker. Returns the value (`None` if
+    /// all its bits are set, which means an unknown size) and the length in bytes.
+    fn vint(&mut self) -> AnyResult<(Option<u64>, u64)> {
+        let first = self.byte()?;
+        let length = first.leading_zeros() as u64 + 1;
+        if length > 8 {
+            bail!("invalid variable size integer");
+        }
+        let mut value = (first as u64) & (0xFF >> length);
+        let mut all_ones = value == 0xFF >> length;
+        for _ in 1..length {
+            let byte = self.byte()?;
+            all_ones &= byte == 0xFF;
+            value = value << 8 | byte as u64;
+        }
+        Ok((if all_ones { None } else { Some(value) }, length))
+    }
+
+    /// Reads a block. Returns the track number, timestamp (relative to the cluster) and frame
+    /// of the block if its track is wanted, otherwise the block is skipped.
+    fn block(
+        &mut self,
+        size: u64,
+        wanted: Option<&HashSet<u64>>,
+    ) -> AnyResult<Option<(u64, i64, Vec<u8>)>> {
+        let (track, length) = self.vint()?;
+        let track = track.ok_or_else(|| anyhow!("invalid block track number"))?;
+        let rest = size
+            .checked_sub(length)
+            .ok_or_else(|| anyhow!("invalid block size"))?;
+        if !wanted.is_some_and(|wanted| wanted.contains(&track)) {
+            self.skip(rest)?;
+            return Ok(None);
+        }
+
+        let data = self.bytes(rest)?;
+        if data.len() < 3 {
+            bail!("invalid block size");
+        }
+        let timestamp = i16::from_be_bytes([data[0], data[1]]) as i64;
+        let lacing = (data[2] >> 1) & 0b11;
+        if lacing != 0 {
+            bail!("laced blocks in track {} are not supported", track);
+        }
+        Ok(Some((track, timestamp, data[3..].to_vec())))
+    }
+
+    /// Reads the children of a master element of `size` bytes and returns the string value of
+    /// the first child with the given ID.
+    fn child_string(&mut self, size: u64, id: u32) -> AnyResult<Option<String>> {
+        let mut children = EbmlReader::new(Cursor::new(self.bytes(size)?));
+        while let Some((child_id, size)) = children.element_header()? {
+            let size =
+                size.ok_or_else(|| anyhow!("element {:#X} has an unknown size", child_id))?;
+            if child_id == id {
+                return Ok(Some(children.string(size)?));
+            }
+            children.skip(size)?;
+        }
+        Ok(None)
+    }
+
+    fn byte(&mut self) -> AnyResult<u8> {
+        let mut byte = [0];
+        self.reader.read_exact(&mut byte).map_err(unexpected_eof)?;
+        Ok(byte[0])
+    }
+
+    fn bytes(&mut self, size: u64) -> AnyResult<Vec<u8>> {
+        if size > MAX_ELEMENT_SIZE {
+            bail!("element of {} bytes is too large", size);
+        }
+        let mut data = vec![0; size as usize];
+        self.reader.read_exact(&mut data).map_err(unexpected_eof)?;
+        Ok(data)
+    }
+
+    fn uint(&mut self, size: u64) -> AnyResult<u64> {
+        if size > 8 {
+            bail!("invalid unsigned integer of {} bytes", size);
+        }
+        Ok(self
+            .bytes(size)?
+            .iter()
+            .fold(0, |value, &byte| value << 8 | byte as u64))
+    }
+
+    fn float(&mut self, size: u64) -> AnyResult<f64> {
+        let data = self.bytes(size)?;
+        Ok(match data.len() {
+            0 => 0.0,
+            4 => f32::from_be_bytes(data.try_into().unwrap()) as f64,
+            8 => f64::from_be_bytes(data.try_into().unwrap()),
+            _ => bail!("invalid float of {} bytes", size),
+        })
+    }
+
+    fn string(&mut self, size: u64) -> AnyResult<String> {
+        Ok(string(&self.bytes(size)?))
+    }
+
+    fn skip(&mut self, size: u64) -> AnyResult<()> {
+        let size = i64::try_from(size).map_err(|_| anyhow!("invalid element size"))?;
+        self.reader.seek_relative(size)?;
+        Ok(())
+    }
+}
+
+/// Strings are UTF-8 (or ASCII) and may be padded with zero bytes.
+fn string(data: &[u8]) -> String {
+    String::from_utf8_lossy(data)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+fn unexpected_eof(e: io::Error) -> anyhow::Error {
+    if e.kind() == ErrorKind::UnexpectedEof {
+        anyhow!("unexpected end of file")
+    } else {
+        e.into()
+    }
+}
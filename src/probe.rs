@@ -0,0 +1,89 @@
+//! Reads the duration, frame rate and tracks of Matroska and MP4 video files from their headers.
+
+mod mp4;
+
+use crate::mkv;
+use anyhow::Result as AnyResult;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoInfo {
+    /// The duration in milliseconds.
+    pub duration: Option<i64>,
+    /// The frame rate of the first video track.
+    pub fps: Option<f64>,
+    pub tracks: Vec<TrackInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackInfo {
+    pub kind: TrackKind,
+    /// The codec as named by the container, e.g. S_TEXT/UTF8 or avc1.
+    pub codec: String,
+    pub language: Option<String>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TrackKind {
+    Video,
+    Audio,
+    Subtitle,
+    Other,
+}
+
+impl VideoInfo {
+    pub fn subtitle_tracks(&self) -> usize {
+        self.tracks
+            .iter()
+            .filter(|track| track.kind == TrackKind::Subtitle)
+            .count()
+    }
+}
+
+/// Reads the info of a Matroska or MP4 file. Fails for other files.
+pub fn probe(path: &Path) -> AnyResult<VideoInfo> {
+    let mut magic = [0; 8];
+    File::open(path)?
+        .read_exact(&mut magic)
+        .map_err(|_| anyhow!("not a video file"))?;
+
+    if magic[..4] == [0x1A, 0x45, 0xDF, 0xA3] {
+        probe_mkv(path)
+    } else if mp4::is_mp4(&magic) {
+        mp4::probe(path)
+    } else {
+        bail!("not a Matroska or MP4 file")
+    }
+}
+
+fn probe_mkv(path: &Path) -> AnyResult<VideoInfo> {
+    let info = mkv::read_info(path)?;
+    let fps = info
+        .tracks
+        .iter()
+        .find(|track| track.track_type == mkv::VIDEO_TRACK)
+        .and_then(|track| track.default_duration)
+        .filter(|&duration| duration > 0)
+        .map(|duration| 1_000_000_000.0 / duration as f64);
+
+    Ok(VideoInfo {
+        duration: info.duration,
+        fps,
+        tracks: info
+            .tracks
+            .iter()
+            .map(|track| TrackInfo {
+                kind: match track.track_type {
+                    mkv::VIDEO_TRACK => TrackKind::Video,
+                    mkv::AUDIO_TRACK => TrackKind::Audio,
+                    mkv::SUBTITLE_TRACK => TrackKind::Subtitle,
+                    _ => TrackKind::Other,
+                },
+                codec: track.codec.clone(),
+                language: Some(track.language().to_string()),
+            })
+            .collect(),
+    })
+}
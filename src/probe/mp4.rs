@@ -0,0 +1,208 @@
+//! Reads the movie box (moov) of MP4 and QuickTime files.
+
+use super::{TrackInfo, TrackKind, VideoInfo};
+use anyhow::Result as AnyResult;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// The movie box is read into memory, so larger ones are treated as a broken file.
+const MAX_MOOV_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Box types that an MP4 file can start with.
+const FIRST_BOXES: &[&[u8; 4]] = &[b"ftyp", b"moov", b"mdat", b"free", b"skip", b"wide"];
+
+pub fn is_mp4(magic: &[u8; 8]) -> bool {
+    FIRST_BOXES.iter().any(|typ| magic[4..] == typ[..])
+}
+
+pub fn probe(path: &Path) -> AnyResult<VideoInfo> {
+    let moov = read_moov(path)?;
+
+    let mvhd = find(&moov, b"mvhd")?.ok_or_else(|| anyhow!("missing mvhd box"))?;
+    let (timescale, duration, _) = media_header(mvhd)?;
+
+    let mut tracks = Vec::new();
+    let mut fps = None;
+    for (typ, trak) in boxes(&moov)? {
+        if &typ != b"trak" {
+            continue;
+        }
+        let Some(mdia) = find(trak, b"mdia")? else {
+            continue;
+        };
+        let kind = match find(mdia, b"hdlr")?.and_then(|hdlr| hdlr.get(8..12)) {
+            Some(b"vide") => TrackKind::Video,
+            Some(b"soun") => TrackKind::Audio,
+            Some(b"sbtl" | b"subt" | b"text" | b"clcp") => TrackKind::Subtitle,
+            _ => TrackKind::Other,
+        };
+        let (track_timescale, _, language) = match find(mdia, b"mdhd")? {
+            Some(mdhd) => media_header(mdhd)?,
+            None => (0, None, None),
+        };
+        let stbl = match find(mdia, b"minf")? {
+            Some(minf) => find(minf, b"stbl")?,
+            None => None,
+        };
+        // The format of the first sample description.
+        let codec = stbl
+            .map(|stbl| find(stbl, b"stsd"))
+            .transpose()?
+            .flatten()
+            .and_then(|stsd| stsd.get(12..16))
+            .map(|format| String::from_utf8_lossy(format).trim().to_string())
+            .unwrap_or_default();
+
+        if kind == TrackKind::Video
+            && fps.is_none()
+            && let Some(stts) = stbl.map(|stbl| find(stbl, b"stts")).transpose()?.flatten()
+        {
+            fps = frame_rate(stts, track_timescale);
+        }
+        tracks.push(TrackInfo {
+            kind,
+            codec,
+            language,
+        });
+    }
+
+    Ok(VideoInfo {
+        duration: duration
+            .filter(|_| timescale > 0)
+            .map(|duration| (duration as u128 * 1000 / timescale as u128) as i64),
+        fps,
+        tracks,
+    })
+}
+
+/// Finds the moov box among the top level boxes, skipping the others (e.g. the media data,
+/// which comes before the moov box in some files).
+fn read_moov(path: &Path) -> AnyResult<Vec<u8>> {
+    let mut file = BufReader::new(File::open(path)?);
+    loop {
+        let mut header = [0; 8];
+        match file.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => bail!("missing moov box"),
+            Err(e) => return Err(e.into()),
+        }
+        let mut size = u32::from_be_bytes(header[..4].try_into().unwrap()) as u64;
+        let mut header_size = 8;
+        if size == 1 {
+            let mut large_size = [0; 8];
+            file.read_exact(&mut large_size)?;
+            size = u64::from_be_bytes(large_size);
+            header_size = 16;
+        } else if size == 0 {
+            // The box extends to the end of the file.
+            let position = file.stream_position()?;
+            size = file.seek(SeekFrom::End(0))? - position + header_size;
+            file.seek(SeekFrom::Start(position))?;
+        }
+        let payload_size = size
+            .checked_sub(header_size)
+            .ok_or_else(|| anyhow!("invalid box size"))?;
+
+        if &header[4..] == b"moov" {
+            if payload_size > MAX_MOOV_SIZE {
+                bail!("moov box of {} bytes is too large", payload_size);
+            }
+            let mut moov = vec![0; payload_size as usize];
+            file.read_exact(&mut moov)?;
+            return Ok(moov);
+        }
+        let payload_size = i64::try_from(payload_size).map_err(|_| anyhow!("invalid box size"))?;
+        file.seek_relative(payload_size)?;
+    }
+}
+
+/// Returns the type and payload of the boxes in `data`.
+fn boxes(mut data: &[u8]) -> AnyResult<Vec<([u8; 4], &[u8])>> {
+    let mut boxes = Vec::new();
+    while data.len() >= 8 {
+        let mut size = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+        let typ: [u8; 4] = data[4..8].try_into().unwrap();
+        let mut header_size = 8;
+        if size == 1 {
+            let large_size = data.get(8..16).ok_or_else(|| anyhow!("invalid box size"))?;
+            size = usize::try_from(u64::from_be_bytes(large_size.try_into().unwrap()))?;
+            header_size = 16;
+        } else if size == 0 {
+            size = data.len();
+        }
+        if size < header_size || size > data.len() {
+            bail!("invalid size of {} box", String::from_utf8_lossy(&typ));
+        }
+        boxes.push((typ, &data[header_size..size]));
+        data = &data[size..];
+    }
+    Ok(boxes)
+}
+
+/// Returns the payload of the first box of the given type in `data`.
+fn find<'a>(data: &'a [u8], typ: &[u8; 4]) -> AnyResult<Option<&'a [u8]>> {
+    Ok(boxes(data)?
+        .into_iter()
+        .find(|(t, _)| t == typ)
+        .map(|(_, payload)| payload))
+}
+
+/// Reads the timescale, duration and language of a mvhd or mdhd box. The duration is `None` if
+/// it is unknown.
+fn media_header(data: &[u8]) -> AnyResult<(u32, Option<u64>, Option<String>)> {
+    let u32_at = |offset: usize| {
+        data.get(offset..offset + 4)
+            .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+    };
+    let u64_at = |offset: usize| {
+        data.get(offset..offset + 8)
+            .map(|bytes| u64::from_be_bytes(bytes.try_into().unwrap()))
+    };
+
+    // Version 1 has 64 bit creation and modification times and duration.
+    let (timescale, duration, language_offset) = match data.first() {
+        Some(1) => (u32_at(20), u64_at(24).filter(|&d| d != u64::MAX), 32),
+        _ => (
+            u32_at(12),
+            u32_at(16).filter(|&d| d != u32::MAX).map(u64::from),
+            20,
+        ),
+    };
+    let timescale = timescale.ok_or_else(|| anyhow!("invalid media header"))?;
+
+    // Three 5 bit letters (ISO 639-2/T) offset by 0x60. mvhd has no language.
+    let language = data
+        .get(language_offset..language_offset + 2)
+        .map(|bytes| u16::from_be_bytes(bytes.try_into().unwrap()))
+        .filter(|&code| code != 0)
+        .map(|code| {
+            [10, 5, 0]
+                .iter()
+                .map(|shift| (((code >> shift) & 0x1F) as u8 + 0x60) as char)
+                .collect::<String>()
+        })
+        .filter(|language| language.chars().all(|c| c.is_ascii_lowercase()));
+
+    Ok((timescale, duration.filter(|&d| d > 0), language))
+}
+
+/// Calculates the average frame rate from the sample durations in a stts box.
+fn frame_rate(stts: &[u8], timescale: u32) -> Option<f64> {
+    let count = u32::from_be_bytes(stts.get(4..8)?.try_into().ok()?) as usize;
+    let (frames, duration) = stts
+        .get(8..8 + count * 8)?
+        .chunks_exact(8)
+        .map(|entry| {
+            let frames = u32::from_be_bytes(entry[..4].try_into().unwrap()) as u64;
+            let delta = u32::from_be_bytes(entry[4..].try_into().unwrap()) as u64;
+            (frames, frames * delta)
+        })
+        .fold((0, 0), |(f, d), (frames, duration)| {
+            (f + frames, d + duration)
+        });
+    if duration == 0 || timescale == 0 {
+        return None;
+    }
+    Some(frames as f64 * timescale as f64 / duration as f64)
+}
@@ -1,6 +1,7 @@
 use crate::config::{GlobalConfig, MatchFilesConfig};
+use crate::probe::{self, VideoInfo};
 use anyhow::Result as AnyResult;
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 use regex::Regex;
 use std::collections::HashMap;
 use std::ffi::{OsStr, OsString};
@@ -32,6 +33,9 @@ pub struct MatchInfo {
     pub video_file_name: String,
 
     pub match_type: MatchInfoType,
+
+    /// The probed info of the video, see `video_info`.
+    video_info: OnceCell<Option<VideoInfo>>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -61,9 +65,18 @@ impl MatchInfo {
             sub_file_ext: sub.ext.clone().unwrap(),
             video_file_name: video.file_name.clone(),
             match_type: MatchInfoType::Identical,
+            video_info: OnceCell::new(),
         }
     }
 
+    /// Returns the duration, frame rate and tracks of the video. The video is probed the first
+    /// time this is called. Returns `None` if the video isn't a Matroska or MP4 file.
+    pub fn video_info(&self) -> Option<&VideoInfo> {
+        self.video_info
+            .get_or_init(|| probe::probe(&self.video_path).ok())
+            .as_ref()
+    }
+
     fn from_number_ranges(
         sub: &FileInfo,
         video: &FileInfo,
@@ -174,6 +187,22 @@ pub fn scan_subs_only(options: ScanOptions) -> AnyResult<Vec<PathBuf>> {
     Ok(subs)
 }
 
+/// Returns the files that have one of the given extensions (ignoring case) and match the video
+/// filter.
+pub fn scan_videos_only(options: ScanOptions, extensions: &[&str]) -> AnyResult<Vec<PathBuf>> {
+    let videos = entries(options.path)?
+        .into_iter()
+        .map(|e| e.path())
+        .filter(|p| {
+            let ext = p.extension().and_then(OsStr::to_str).unwrap_or_default();
+            p.is_file() && extensions.iter().any(|e| e.eq_ignore_ascii_case(ext))
+        })
+        .filter(|video| regex_matches_file_name(options.video_filter, video))
+        .collect();
+
+    Ok(videos)
+}
+
 fn entries(path: &Path) -> io::Result<Vec<DirEntry>> {
     std::fs::read_dir(path)?.collect::<io::Result<Vec<DirEntry>>>()
 }
@@ -432,6 +461,14 @@ fn parse_file_info<'a>(
         .collect::<AnyResult<_>>()
 }
 
+/// Returns the secondary extension of the file (e.g. en for sub.en.srt) as it is parsed when
+/// matching files with the given policy.
+pub fn secondary_extension(path: &Path, policy: SecondaryExtensionPolicy) -> Option<String> {
+    let (_, ext) = split_extension(path, policy)?;
+    let (secondary, _) = ext.to_str()?.split_once('.')?;
+    Some(secondary.to_string())
+}
+
 fn split_extension(
     path: &Path,
     secondary_ext_policy: SecondaryExtensionPolicy,
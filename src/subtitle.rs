@@ -0,0 +1,433 @@
+pub mod ass;
+pub mod atomic;
+pub mod encoding;
+pub mod microdvd;
+pub mod srt;
+pub mod vtt;
+
+use crate::config::StretchConfig;
+use anyhow::Result as AnyResult;
+use encoding::Detection;
+use encoding_rs::Encoding;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use subparse::timetypes::{TimeDelta, TimePoint, TimeSpan};
+use subparse::{SubtitleEntry, SubtitleFile, SubtitleFormat};
+use vtt::VttFile;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Format {
+    Subparse(SubtitleFormat),
+    WebVtt,
+}
+
+impl Format {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Subparse(SubtitleFormat::SubRip) => "SubRip",
+            Format::Subparse(SubtitleFormat::SubStationAlpha) => "SSA/ASS",
+            Format::Subparse(SubtitleFormat::VobSubIdx) => "VobSub IDX",
+            Format::Subparse(SubtitleFormat::VobSubSub) => "VobSub",
+            Format::Subparse(SubtitleFormat::MicroDVD) => "MicroDVD",
+            Format::WebVtt => "WebVTT",
+        }
+    }
+}
+
+/// A parsed subtitle file. The entries can be changed and are written back to the file with
+/// `write`.
+pub struct ParsedSub {
+    pub path: PathBuf,
+    pub format: Format,
+    pub entries: Vec<SubtitleEntry>,
+    /// The encoding the file was read with.
+    pub encoding: &'static Encoding,
+    pub detection: Detection,
+    /// If set the file is re-encoded to this encoding when written.
+    pub output_encoding: Option<&'static Encoding>,
+    /// If set the original file is kept with this suffix appended to its name when written.
+    pub backup: Option<String>,
+    /// The path that the file is written to, `path` by default.
+    pub out_path: PathBuf,
+    layout: Layout,
+    /// The decoded content of the file, `None` for binary formats.
+    text: Option<String>,
+    /// The frame rate that frame based formats are written with.
+    write_fps: f64,
+    /// The index in the file of each entry in `entries`. Entries that are removed with
+    /// `remove_entries` are not in this list, and `sort_entries` changes its order.
+    kept: Vec<usize>,
+    file: SubFile,
+}
+
+/// How the text of a file is laid out apart from its content. subparse (and our WebVTT writer)
+/// always write LF line endings without a BOM, so this is recorded when a file is read and
+/// reproduced when it is written.
+#[derive(Debug, Clone, Default)]
+struct Layout {
+    bom: bool,
+    crlf: bool,
+    /// The number of line breaks at the end of the file.
+    trailing_newlines: usize,
+}
+
+impl Layout {
+    fn of(content: &[u8], text: &str) -> Self {
+        let lf = text.matches('\n').count();
+        let crlf = text.matches("\r\n").count();
+        Layout {
+            bom: Encoding::for_bom(content).is_some(),
+            crlf: crlf * 2 > lf,
+            trailing_newlines: text[text.trim_end_matches(['\r', '\n']).len()..]
+                .matches('\n')
+                .count(),
+        }
+    }
+}
+
+/// WebVTT is parsed with our own parser and all other formats are parsed with subparse.
+enum SubFile {
+    Subparse(SubtitleFile),
+    WebVtt(VttFile),
+}
+
+impl ParsedSub {
+    pub fn read(path: &Path, encoding: Option<&'static Encoding>, fps: f64) -> AnyResult<Self> {
+        Self::read_with_fps(path, encoding, fps, fps)
+    }
+
+    /// Frame based formats are read with `read_fps` and written with `write_fps`, other formats
+    /// ignore the frame rate. The encoding is detected if `encoding` is `None` or the file starts
+    /// with a BOM.
+    pub fn read_with_fps(
+        path: &Path,
+        encoding: Option<&'static Encoding>,
+        read_fps: f64,
+        write_fps: f64,
+    ) -> AnyResult<Self> {
+        let content = fs::read(path)?;
+        let (encoding, detection) = match encoding {
+            // A BOM names the encoding of this file, while --encoding is given for all of them.
+            Some(encoding) if Encoding::for_bom(&content).is_none() => (encoding, Detection::Given),
+            _ => encoding::detect(&content),
+        };
+
+        if path.extension().is_some_and(|ext| ext == "vtt") {
+            let text = encoding::decode(&content, encoding)?;
+            let file = VttFile::parse(&text)?;
+            let entries = file.get_subtitle_entries();
+            return Ok(Self {
+                path: path.to_path_buf(),
+                format: Format::WebVtt,
+                kept: (0..entries.len()).collect(),
+                entries,
+                encoding,
+                detection,
+                output_encoding: None,
+                backup: None,
+                out_path: path.to_path_buf(),
+                layout: Layout::of(&content, &text),
+                text: Some(text),
+                write_fps,
+                file: SubFile::WebVtt(file),
+            });
+        }
+
+        let format = subparse::get_subtitle_format(path.extension(), &content)
+            .ok_or_else(|| anyhow!("invalid subtitle format: {:?}", path.extension()))?;
+
+        // VobSub .sub files are binary so only decode the text based formats.
+        let text = if format == SubtitleFormat::VobSubSub {
+            None
+        } else {
+            Some(encoding::decode(&content, encoding)?)
+        };
+
+        let file = parse(format, &content, text.as_deref(), read_fps)?;
+        let mut entries = file
+            .get_subtitle_entries()
+            .map_err(|e| anyhow!("failed to get subtitle entries: {:?}", e))?;
+
+        let file = if format == SubtitleFormat::MicroDVD && write_fps != read_fps {
+            // The entries are read with the source frame rate and are written back into a file
+            // that converts them to frames with the target frame rate. subparse truncates when
+            // converting to frames so we add half a frame to round to the nearest frame instead.
+            let half_frame = TimeDelta::from_msecs((500.0 / write_fps) as i64);
+            for entry in &mut entries {
+                entry.timespan += half_frame;
+            }
+            parse(format, &content, text.as_deref(), write_fps)?
+        } else {
+            file
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            format: Format::Subparse(format),
+            kept: (0..entries.len()).collect(),
+            entries,
+            encoding,
+            detection,
+            output_encoding: None,
+            backup: None,
+            out_path: path.to_path_buf(),
+            layout: text
+                .as_ref()
+                .map(|text| Layout::of(&content, text))
+                .unwrap_or_default(),
+            text,
+            write_fps,
+            file: SubFile::Subparse(file),
+        })
+    }
+
+    /// The encoding that the file is written with: the output encoding if one is set, otherwise
+    /// the encoding that the file was read with.
+    pub fn write_encoding(&self) -> &'static Encoding {
+        self.output_encoding.unwrap_or(self.encoding)
+    }
+
+    pub fn write(&mut self) -> AnyResult<()> {
+        if self.entries.len() != self.kept.len() {
+            bail!(
+                "{:?}: entries can only be removed with remove_entries",
+                self.path
+            );
+        }
+
+        let in_file_order = self.kept.iter().enumerate().all(|(i, &index)| i == index);
+        let data = match &mut self.file {
+            SubFile::Subparse(file)
+                if in_file_order && self.kept.len() == file_entry_count(file)? =>
+            {
+                // subparse rewrites all of an SRT file, so if only the timings changed they are
+                // replaced in the original text instead.
+                match retime_srt(file, self.text.as_deref(), &self.entries)? {
+                    Some(text) => text.into_bytes(),
+                    None => {
+                        file.update_subtitle_entries(&self.entries)
+                            .map_err(|e| anyhow!("failed to update subtitle entries: {:?}", e))?;
+                        file.to_data()
+                            .map_err(|e| anyhow!("failed to get subtitle data: {:?}", e))?
+                    }
+                }
+            }
+            SubFile::Subparse(SubtitleFile::SubRipFile(_)) => {
+                let cues = self
+                    .entries
+                    .iter()
+                    .map(|entry| (entry.timespan, entry.line.clone().unwrap_or_default()))
+                    .collect();
+                srt::write(cues)?
+            }
+            SubFile::Subparse(file @ SubtitleFile::SubStationAlpha(_)) => {
+                // subparse can't remove events, so the removed events are written unchanged and
+                // their lines are then removed. The kept events stay in their place in the file.
+                let mut entries = file
+                    .get_subtitle_entries()
+                    .map_err(|e| anyhow!("failed to get subtitle entries: {:?}", e))?;
+                for (&index, entry) in self.kept.iter().zip(&self.entries) {
+                    entries[index] = SubtitleEntry {
+                        timespan: entry.timespan,
+                        line: entry.line.clone(),
+                    };
+                }
+                file.update_subtitle_entries(&entries)
+                    .map_err(|e| anyhow!("failed to update subtitle entries: {:?}", e))?;
+                let data = file
+                    .to_data()
+                    .map_err(|e| anyhow!("failed to get subtitle data: {:?}", e))?;
+                let kept = self.kept.iter().collect::<HashSet<_>>();
+                ass::retain_events(&String::from_utf8(data)?, |index| kept.contains(&index))
+                    .into_bytes()
+            }
+            SubFile::Subparse(SubtitleFile::MicroDVDFile(_)) => {
+                // The removed lines are removed from the original text, which is then parsed
+                // again to get a file with only the kept lines. subparse writes the lines
+                // ordered by time, so the order of the entries doesn't matter.
+                let kept = self.kept.iter().collect::<HashSet<_>>();
+                let text =
+                    microdvd::retain_lines(self.text.as_deref().unwrap_or_default(), |index| {
+                        kept.contains(&index)
+                    });
+                let mut file = parse(
+                    SubtitleFormat::MicroDVD,
+                    text.as_bytes(),
+                    Some(&text),
+                    self.write_fps,
+                )?;
+                let mut entries = self.kept.iter().zip(&self.entries).collect::<Vec<_>>();
+                entries.sort_by_key(|(index, _)| **index);
+                let entries = entries
+                    .into_iter()
+                    .map(|(_, entry)| SubtitleEntry {
+                        timespan: entry.timespan,
+                        line: entry.line.clone(),
+                    })
+                    .collect::<Vec<_>>();
+                file.update_subtitle_entries(&entries)
+                    .map_err(|e| anyhow!("failed to update subtitle entries: {:?}", e))?;
+                file.to_data()
+                    .map_err(|e| anyhow!("failed to get subtitle data: {:?}", e))?
+            }
+            SubFile::Subparse(_) => {
+                bail!(
+                    "{:?}: entries can't be removed from or reordered in image based subtitles",
+                    self.path
+                )
+            }
+            SubFile::WebVtt(file) => {
+                file.select_cues(&self.kept);
+                self.kept = (0..self.entries.len()).collect();
+                file.update_subtitle_entries(&self.entries)?;
+                file.to_data()
+            }
+        };
+
+        let data = if self.format == Format::Subparse(SubtitleFormat::VobSubSub) {
+            data
+        } else {
+            let text = String::from_utf8(data)?.replace("\r\n", "\n");
+            let text = text.trim_end_matches('\n').to_string()
+                + &"\n".repeat(self.layout.trailing_newlines);
+            self.encode(&text)?
+        };
+
+        atomic::write_file(&self.out_path, &data, self.backup.as_deref())
+    }
+
+    /// Encodes text that is written to the file (or to a file converted from it) with the BOM
+    /// and line endings of the file.
+    pub fn encode(&self, text: &str) -> AnyResult<Vec<u8>> {
+        let encoding = self.write_encoding();
+        let text = if self.layout.crlf {
+            text.replace("\r\n", "\n").replace('\n', "\r\n")
+        } else {
+            text.to_string()
+        };
+
+        encoding::encode_file(&text, encoding, self.layout.bom)
+            .map_err(|e| anyhow!("{:?}: {}", self.path, e))
+    }
+
+    /// Returns the timespans and texts of all entries with the text converted to SRT markup.
+    /// Fails for image based formats.
+    pub fn text_cues(&self) -> AnyResult<Vec<(TimeSpan, String)>> {
+        self.entries
+            .iter()
+            .map(|entry| {
+                let line = entry.line.as_ref().ok_or_else(|| {
+                    anyhow!("{:?} is an image based subtitle and has no text", self.path)
+                })?;
+                let text = match self.format {
+                    Format::Subparse(SubtitleFormat::SubStationAlpha) => ass::to_srt_markup(line),
+                    Format::WebVtt => vtt::to_srt_markup(line),
+                    _ => line.clone(),
+                };
+                Ok((entry.timespan, text))
+            })
+            .collect()
+    }
+
+    /// Removes the entries that `remove` returns true for. `remove` gets the index of the entry
+    /// in `entries` and the entry. The entries are removed from the file when it is written.
+    /// Returns the number of removed entries.
+    pub fn remove_entries(&mut self, remove: impl Fn(usize, &SubtitleEntry) -> bool) -> usize {
+        let before = self.entries.len();
+        let (kept, entries) = self
+            .kept
+            .iter()
+            .zip(self.entries.drain(..))
+            .enumerate()
+            .filter(|(i, (_, entry))| !remove(*i, entry))
+            .map(|(_, kept_entry)| kept_entry)
+            .unzip();
+        self.kept = kept;
+        self.entries = entries;
+        before - self.entries.len()
+    }
+
+    /// Sorts the entries by start time, keeping the order of entries that start at the same time.
+    /// The entries are moved in the file when it is written (SSA files keep their event order,
+    /// as players sort the events anyway). Returns true if the order changed.
+    pub fn sort_entries(&mut self) -> bool {
+        if self.entries.is_sorted_by_key(|entry| entry.timespan.start) {
+            return false;
+        }
+        let mut sorted = self
+            .kept
+            .drain(..)
+            .zip(self.entries.drain(..))
+            .collect::<Vec<_>>();
+        sorted.sort_by_key(|(_, entry)| entry.timespan.start);
+        (self.kept, self.entries) = sorted.into_iter().unzip();
+        true
+    }
+
+    /// Returns the index in the file of the entry at `index` in `entries`.
+    pub fn file_index(&self, index: usize) -> usize {
+        self.kept[index]
+    }
+
+    /// Stretches (if `stretch` is given) and then shifts all entries by `offset` milliseconds.
+    pub fn transform(&mut self, stretch: Option<&StretchConfig>, offset: i64) {
+        for entry in &mut self.entries {
+            if let Some(stretch) = stretch {
+                entry.timespan = stretch_timespan(entry.timespan, stretch);
+            }
+            entry.timespan += TimeDelta::from_msecs(offset);
+        }
+    }
+}
+
+fn file_entry_count(file: &SubtitleFile) -> AnyResult<usize> {
+    Ok(file
+        .get_subtitle_entries()
+        .map_err(|e| anyhow!("failed to get subtitle entries: {:?}", e))?
+        .len())
+}
+
+/// Returns the text of an SRT file with only the timings of its cues replaced by those of
+/// `entries`. Returns `None` for other formats, or if the text of a cue changed.
+fn retime_srt(
+    file: &SubtitleFile,
+    text: Option<&str>,
+    entries: &[SubtitleEntry],
+) -> AnyResult<Option<String>> {
+    let (SubtitleFile::SubRipFile(_), Some(text)) = (file, text) else {
+        return Ok(None);
+    };
+    let original = file
+        .get_subtitle_entries()
+        .map_err(|e| anyhow!("failed to get subtitle entries: {:?}", e))?;
+    if original.iter().zip(entries).any(|(a, b)| a.line != b.line) {
+        return Ok(None);
+    }
+    let timespans = entries
+        .iter()
+        .map(|entry| entry.timespan)
+        .collect::<Vec<_>>();
+    Ok(srt::retime(text, &timespans))
+}
+
+fn parse(
+    format: SubtitleFormat,
+    content: &[u8],
+    text: Option<&str>,
+    fps: f64,
+) -> AnyResult<SubtitleFile> {
+    match text {
+        Some(text) => subparse::parse_str(format, text, fps),
+        None => subparse::parse_bytes(format, content, None, fps),
+    }
+    .map_err(|e| anyhow!("failed to parse subtitle file: {:?}", e))
+}
+
+fn stretch_timespan(timespan: TimeSpan, stretch: &StretchConfig) -> TimeSpan {
+    let scale = |point: TimePoint| {
+        let msecs = (point.msecs() - stretch.anchor) as f64 * stretch.factor;
+        TimePoint::from_msecs(stretch.anchor + msecs.round() as i64)
+    };
+    TimeSpan::new(scale(timespan.start), scale(timespan.end))
+}
@@ -0,0 +1,165 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::fmt::Write;
+use subparse::timetypes::{TimePoint, TimeSpan};
+
+static OVERRIDE_BLOCK: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{[^}]*\}").unwrap());
+static BASIC_OVERRIDE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\\([ibu])([01])$").unwrap());
+static BASIC_HTML_TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)<(/?)([ibu])>").unwrap());
+
+#[derive(Debug, Clone)]
+pub struct AssStyle {
+    pub name: String,
+    pub font: String,
+    pub font_size: u32,
+    /// Numpad style alignment (2 = bottom center, 8 = top center).
+    pub alignment: u8,
+    pub margin_v: u32,
+    /// RGB color of the text.
+    pub color: u32,
+}
+
+impl AssStyle {
+    pub fn new(name: &str, font: &str, font_size: u32) -> Self {
+        AssStyle {
+            name: name.to_string(),
+            font: font.to_string(),
+            font_size,
+            alignment: 2,
+            margin_v: 40,
+            color: 0xFFFFFF,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct AssEvent<'a> {
+    pub timespan: TimeSpan,
+    pub style: &'a str,
+    /// The text with ASS markup (see `from_srt_markup`).
+    pub text: String,
+}
+
+/// Creates a .ass file with the given styles and events.
+pub fn write(styles: &[AssStyle], events: &[AssEvent]) -> Vec<u8> {
+    let mut out = String::new();
+    out.push_str("[Script Info]\n");
+    out.push_str("ScriptType: v4.00+\n");
+    out.push_str("PlayResX: 1920\n");
+    out.push_str("PlayResY: 1080\n");
+    out.push_str("WrapStyle: 0\n");
+    out.push_str("ScaledBorderAndShadow: yes\n\n");
+
+    out.push_str("[V4+ Styles]\n");
+    out.push_str(
+        "Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, \
+        BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, \
+        BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\n",
+    );
+    for style in styles {
+        let _ = writeln!(
+            out,
+            "Style: {},{},{},&H00{:06X},&H000000FF,&H00000000,&H80000000,0,0,0,0,100,100,0,0,\
+            1,3,1,{},60,60,{},1",
+            style.name,
+            style.font,
+            style.font_size,
+            bgr(style.color),
+            style.alignment,
+            style.margin_v
+        );
+    }
+
+    out.push_str("\n[Events]\n");
+    out.push_str(
+        "Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n",
+    );
+    for event in events {
+        let _ = writeln!(
+            out,
+            "Dialogue: 0,{},{},{},,0,0,0,,{}",
+            timestamp(event.timespan.start),
+            timestamp(event.timespan.end),
+            event.style,
+            event.text
+        );
+    }
+
+    out.into_bytes()
+}
+
+/// Removes the `Dialogue` events of an SSA/ASS file that `keep` returns false for. `keep` gets
+/// the index of an event in the order that subparse reads them. Everything else is kept.
+pub fn retain_events(text: &str, keep: impl Fn(usize) -> bool) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut section = "";
+    let mut index = 0;
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') && trimmed.ends_with(']') {
+            section = &trimmed[1..trimmed.len() - 1];
+        } else if section == "Events" && trimmed.starts_with("Dialogue:") {
+            index += 1;
+            if !keep(index - 1) {
+                continue;
+            }
+        }
+        result.push_str(line);
+    }
+    result
+}
+
+/// ASS colors are written in BGR order.
+fn bgr(rgb: u32) -> u32 {
+    ((rgb & 0xFF) << 16) | (rgb & 0xFF00) | ((rgb >> 16) & 0xFF)
+}
+
+/// Converts ASS markup to SRT markup. Line breaks and hard spaces are converted, italic, bold
+/// and underline overrides are converted to HTML tags and all other override tags are removed.
+pub fn to_srt_markup(text: &str) -> String {
+    let text = OVERRIDE_BLOCK.replace_all(text, |caps: &regex::Captures| {
+        let block = &caps[0][1..caps[0].len() - 1];
+        block
+            .split('\\')
+            .filter(|tag| !tag.is_empty())
+            .filter_map(|tag| {
+                let tag = format!("\\{}", tag);
+                let caps = BASIC_OVERRIDE.captures(&tag)?;
+                let close = if &caps[2] == "0" { "/" } else { "" };
+                Some(format!("<{}{}>", close, &caps[1]))
+            })
+            .collect::<String>()
+    });
+    text.replace("\\N", "\n")
+        .replace("\\n", "\n")
+        .replace("\\h", " ")
+}
+
+/// Converts SRT markup to ASS markup. Line breaks and italic, bold and underline tags are
+/// converted and all other HTML tags are removed.
+pub fn from_srt_markup(text: &str) -> String {
+    let text = BASIC_HTML_TAG.replace_all(text, |caps: &regex::Captures| {
+        let on = if caps[1].is_empty() { "1" } else { "0" };
+        format!("{{\\{}{}}}", caps[2].to_lowercase(), on)
+    });
+    super::srt::strip_tags(&text, &[])
+        .lines()
+        .collect::<Vec<_>>()
+        .join("\\N")
+}
+
+/// Formats a time as an SSA/ASS timestamp (`h:mm:ss.cc`).
+pub fn timestamp(t: TimePoint) -> String {
+    let t = if t.msecs() < 0 {
+        TimePoint::from_msecs(0)
+    } else {
+        t
+    };
+    format!(
+        "{}:{:02}:{:02}.{:02}",
+        t.hours(),
+        t.mins_comp(),
+        t.secs_comp(),
+        t.csecs_comp()
+    )
+}
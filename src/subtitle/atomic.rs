@@ -0,0 +1,128 @@
+use anyhow::Result as AnyResult;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, ErrorKind, Write};
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use tempfile::NamedTempFile;
+
+/// The temporary files that exist, which are removed if sub-batch is interrupted. `None` once
+/// they have been removed, after which no temporary files can be created.
+static TEMP_FILES: Mutex<Option<BTreeSet<PathBuf>>> = Mutex::new(Some(BTreeSet::new()));
+
+/// A temporary file created by `temp_file_for`. It is removed when it is dropped, and by
+/// `remove_temp_files` if sub-batch is interrupted.
+pub struct TempFile {
+    file: NamedTempFile,
+    _registered: Registered,
+}
+
+impl Deref for TempFile {
+    type Target = NamedTempFile;
+
+    fn deref(&self) -> &NamedTempFile {
+        &self.file
+    }
+}
+
+impl DerefMut for TempFile {
+    fn deref_mut(&mut self) -> &mut NamedTempFile {
+        &mut self.file
+    }
+}
+
+/// Removes a temporary file from `TEMP_FILES` when it is dropped.
+struct Registered(PathBuf);
+
+impl Drop for Registered {
+    fn drop(&mut self) {
+        if let Some(files) = lock().as_mut() {
+            files.remove(&self.0);
+        }
+    }
+}
+
+/// Removes all temporary files. Called when sub-batch is interrupted, since the process then
+/// exits without dropping them. Temporary files can't be created afterwards.
+pub fn remove_temp_files() {
+    for path in lock().take().unwrap_or_default() {
+        let _ = fs::remove_file(path);
+    }
+}
+
+fn lock() -> MutexGuard<'static, Option<BTreeSet<PathBuf>>> {
+    // A panicking thread can't leave the set in an inconsistent state.
+    TEMP_FILES.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Writes `data` to `path` without ever leaving a partially written file behind. See
+/// `replace_file`.
+pub fn write_file(path: &Path, data: &[u8], backup: Option<&str>) -> AnyResult<()> {
+    let mut temp = temp_file_for(path)?;
+    temp.write_all(data)?;
+    replace_file(path, temp, backup)
+}
+
+/// Creates a hidden temporary file in the same directory as `path` (so that it can be renamed
+/// to `path`) with the same extension as `path`. The directory is created if needed.
+pub fn temp_file_for(path: &Path) -> AnyResult<TempFile> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(dir)?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let suffix = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+
+    // The file is created with the lock held so that it can't be missed by
+    // `remove_temp_files`.
+    let mut files = lock();
+    let Some(files) = files.as_mut() else {
+        return Err(io::Error::new(ErrorKind::Interrupted, "interrupted").into());
+    };
+    let file = tempfile::Builder::new()
+        .prefix(&format!(".{}.", name))
+        .suffix(&suffix)
+        .tempfile_in(dir)?;
+    files.insert(file.path().to_path_buf());
+    Ok(TempFile {
+        _registered: Registered(file.path().to_path_buf()),
+        file,
+    })
+}
+
+/// Moves `temp` to `path` with a rename, which replaces `path` in a single step. If `path`
+/// exists its permissions and modification time are kept, and it is first copied to `path`
+/// with `backup` appended to the file name if a backup suffix is given.
+pub fn replace_file(path: &Path, temp: TempFile, backup: Option<&str>) -> AnyResult<()> {
+    temp.as_file().sync_all()?;
+
+    if let Ok(metadata) = fs::metadata(path) {
+        if let Some(suffix) = backup {
+            let mut backup_path = path.as_os_str().to_owned();
+            backup_path.push(suffix);
+            fs::copy(path, &backup_path)?;
+            if let Ok(modified) = metadata.modified() {
+                let _ = fs::File::options()
+                    .write(true)
+                    .open(&backup_path)
+                    .and_then(|file| file.set_modified(modified));
+            }
+        }
+
+        // Best effort: the file is still written if these can't be kept.
+        let _ = temp.as_file().set_permissions(metadata.permissions());
+        if let Ok(modified) = metadata.modified() {
+            let _ = temp.as_file().set_modified(modified);
+        }
+    }
+
+    temp.file
+        .persist(path)
+        .map_err(|e| anyhow!("failed to write {:?}: {}", path, e.error))?;
+    Ok(())
+}
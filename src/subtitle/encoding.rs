@@ -0,0 +1,157 @@
+use anyhow::Result as AnyResult;
+use encoding_rs::{
+    BIG5, EUC_KR, Encoding, GBK, SHIFT_JIS, UTF_8, UTF_16BE, UTF_16LE, WINDOWS_1250, WINDOWS_1251,
+    WINDOWS_1252, WINDOWS_1253, WINDOWS_1254, WINDOWS_1255, WINDOWS_1256, WINDOWS_1257,
+};
+use std::fmt;
+
+// The encodings that are tried (in this order) when a file has no BOM and isn't valid UTF-8.
+// Earlier encodings win ties.
+static CANDIDATES: &[&Encoding] = &[
+    WINDOWS_1252,
+    WINDOWS_1250,
+    WINDOWS_1251,
+    WINDOWS_1253,
+    WINDOWS_1254,
+    WINDOWS_1257,
+    WINDOWS_1255,
+    WINDOWS_1256,
+    SHIFT_JIS,
+    GBK,
+    BIG5,
+    EUC_KR,
+];
+
+// Letters that are common in the languages each single byte encoding is used for, but that
+// decode from bytes that are used for other letters in the other encodings.
+static CHARACTERISTIC_LETTERS: &[(&Encoding, &str)] = &[
+    (
+        WINDOWS_1252,
+        "àâäçéèêëîïôöùûüÿßñáíóúãõœæåÀÂÄÇÉÈÊËÎÏÔÖÙÛÜŸÑÁÍÓÚÃÕŒÆÅ",
+    ),
+    (
+        WINDOWS_1250,
+        "ąćęłńśźżčďěňřšťůžĺľŕőűáéíóúýôäĄĆĘŁŃŚŹŻČĎĚŇŘŠŤŮŽĹĽŔŐŰÁÉÍÓÚÝÔÄ",
+    ),
+    (WINDOWS_1257, "āčēģīķļņšūžąęėįųĀČĒĢĪĶĻŅŠŪŽĄĘĖĮŲ"),
+    (WINDOWS_1254, "çğıöşüÇĞİÖŞÜ"),
+];
+
+/// How the encoding of a file was decided.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Detection {
+    /// Given with --encoding.
+    Given,
+    Bom,
+    /// The file is valid UTF-8.
+    Utf8,
+    /// The encoding was guessed from the character statistics of the file.
+    Guess,
+}
+
+impl fmt::Display for Detection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Detection::Given => "given",
+            Detection::Bom => "detected from BOM",
+            Detection::Utf8 => "detected as valid UTF-8",
+            Detection::Guess => "guessed",
+        })
+    }
+}
+
+/// Detects the encoding of the content from its BOM, by checking if it is valid UTF-8 or
+/// (as a last resort) by guessing it from how the content decodes in common encodings.
+pub fn detect(content: &[u8]) -> (&'static Encoding, Detection) {
+    if let Some((encoding, _)) = Encoding::for_bom(content) {
+        return (encoding, Detection::Bom);
+    }
+    if std::str::from_utf8(content).is_ok() {
+        return (UTF_8, Detection::Utf8);
+    }
+
+    let mut best = (WINDOWS_1252, i64::MIN);
+    for &candidate in CANDIDATES {
+        let (text, had_errors) = candidate.decode_without_bom_handling(content);
+        if had_errors {
+            continue;
+        }
+        let score = score(candidate, &text);
+        if score > best.1 {
+            best = (candidate, score);
+        }
+    }
+    (best.0, Detection::Guess)
+}
+
+/// Scores how plausible it is that `text` is correctly decoded. Letters add to the score and
+/// control characters and unusual symbols subtract from it.
+fn score(encoding: &'static Encoding, text: &str) -> i64 {
+    let characteristic = CHARACTERISTIC_LETTERS
+        .iter()
+        .find(|(e, _)| *e == encoding)
+        .map(|(_, letters)| *letters)
+        .unwrap_or_default();
+
+    text.chars()
+        .filter(|c| !c.is_ascii())
+        .map(|c| {
+            if characteristic.contains(c) {
+                2
+            } else if c.is_alphabetic() {
+                // A multibyte character replaces two single byte characters.
+                if encoding.is_single_byte() { 1 } else { 2 }
+            } else if c.is_control() {
+                -5
+            } else if matches!(
+                c,
+                '\u{a0}' | '…' | '–' | '—' | '‘' | '’' | '“' | '”' | '«' | '»'
+            ) || c.is_whitespace()
+            {
+                0
+            } else {
+                -2
+            }
+        })
+        .sum()
+}
+
+/// Decodes the content with `encoding`, without the BOM of the encoding if it starts with one.
+/// Fails if the content is not valid in the encoding.
+pub fn decode(content: &[u8], encoding: &'static Encoding) -> AnyResult<String> {
+    let (text, had_errors) = encoding.decode_with_bom_removal(content);
+    if had_errors {
+        bail!("the content is not valid {}", encoding.name());
+    }
+    Ok(text.into_owned())
+}
+
+/// Encodes the text with `encoding`. Fails if the text contains characters that can't be
+/// represented in the encoding.
+pub fn encode(text: &str, encoding: &'static Encoding) -> AnyResult<Vec<u8>> {
+    if encoding == UTF_16LE {
+        return Ok(text.encode_utf16().flat_map(u16::to_le_bytes).collect());
+    }
+    if encoding == UTF_16BE {
+        return Ok(text.encode_utf16().flat_map(u16::to_be_bytes).collect());
+    }
+    let (bytes, _, had_unmappable) = encoding.encode(text);
+    if had_unmappable {
+        bail!(
+            "the text contains characters that can't be encoded as {}",
+            encoding.name()
+        );
+    }
+    Ok(bytes.into_owned())
+}
+
+/// Encodes the text of a file with `encoding`, starting with a BOM if `bom` is set and the
+/// encoding can have one. UTF-16 always gets a BOM since it is not usable without one.
+pub fn encode_file(text: &str, encoding: &'static Encoding, bom: bool) -> AnyResult<Vec<u8>> {
+    let mut data = Vec::new();
+    if encoding == UTF_16LE || encoding == UTF_16BE || (bom && encoding == UTF_8) {
+        data.extend(encode("\u{feff}", encoding)?);
+    }
+    data.extend(encode(text, encoding)?);
+    Ok(data)
+}
@@ -0,0 +1,85 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::fmt::Write;
+
+static FRAMES: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\{-?\d+\}\{-?\d+\}").unwrap());
+
+/// Removes the subtitle lines of a MicroDVD file that `keep` returns false for. `keep` gets the
+/// index of a line in the order that subparse reads them, where a line of the file with several
+/// lines separated by `|` has an index for each of them. Formatting that applies to all lines of
+/// a file line (e.g. `{Y:i}`) is kept when the line that has it is removed.
+pub fn retain_lines(text: &str, keep: impl Fn(usize) -> bool) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut index = 0;
+    for line in text.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\r', '\n']);
+        let Some(frames) = FRAMES.find(content) else {
+            result.push_str(line);
+            continue;
+        };
+        let pieces = split_pieces(&content[frames.end()..]);
+        let kept = (index..index + pieces.len()).map(&keep).collect::<Vec<_>>();
+        index += pieces.len();
+        if kept.iter().all(|&kept| kept) {
+            result.push_str(line);
+            continue;
+        }
+        if !kept.iter().any(|&kept| kept) {
+            continue;
+        }
+
+        let moved = pieces
+            .iter()
+            .zip(&kept)
+            .filter(|(_, kept)| !**kept)
+            .flat_map(|(piece, _)| formatting(piece))
+            .filter(|block| block[1..].starts_with(char::is_uppercase))
+            .collect::<String>();
+        let pieces = pieces
+            .iter()
+            .zip(&kept)
+            .filter(|(_, kept)| **kept)
+            .map(|(piece, _)| *piece)
+            .collect::<Vec<_>>();
+        let _ = write!(
+            result,
+            "{}{}{}{}",
+            frames.as_str(),
+            moved,
+            pieces.join("|"),
+            &line[content.len()..]
+        );
+    }
+    result
+}
+
+/// Splits the part of a file line after the frames at the `|` characters that separate its lines.
+/// Like subparse does, a `|` in a formatting block at the start of a line doesn't separate lines.
+fn split_pieces(s: &str) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    loop {
+        let text_start = start + formatting(&s[start..]).map(str::len).sum::<usize>();
+        match s[text_start..].find('|') {
+            Some(i) => {
+                pieces.push(&s[start..text_start + i]);
+                start = text_start + i + 1;
+            }
+            None => {
+                pieces.push(&s[start..]);
+                return pieces;
+            }
+        }
+    }
+}
+
+/// The formatting blocks (e.g. `{y:i}`) at the start of a line.
+fn formatting(line: &str) -> impl Iterator<Item = &str> {
+    let mut rest = line;
+    std::iter::from_fn(move || {
+        let end = rest.strip_prefix('{')?.find('}')? + 2;
+        let (block, tail) = rest.split_at(end);
+        rest = tail;
+        Some(block)
+    })
+}
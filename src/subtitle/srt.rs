@@ -0,0 +1,80 @@
+use anyhow::Result as AnyResult;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use subparse::SrtFile;
+use subparse::timetypes::{TimePoint, TimeSpan};
+
+static HTML_TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"</?([a-zA-Z]+)[^>]*>").unwrap());
+static TIMING_LINE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(\s*)\d+:\d+:\d+,\d+(\s*-->\s*)\d+:\d+:\d+,\d+(\s*)$").unwrap());
+
+/// Creates a .srt file from the cues. The cue texts should use SRT markup.
+pub fn write(cues: Vec<(TimeSpan, String)>) -> AnyResult<Vec<u8>> {
+    let file =
+        SrtFile::create(cues).map_err(|e| anyhow!("failed to create subtitle file: {:?}", e))?;
+    subparse::SubtitleFile::from(file)
+        .to_data()
+        .map_err(|e| anyhow!("failed to get subtitle data: {:?}", e))
+}
+
+/// Replaces the timings of the cues in `text` (the content of a .srt file) with `timespans` and
+/// keeps everything else as it is, including whitespace and blank lines. The timing lines are
+/// found the way subparse finds them. Returns `None` if there isn't a timing line for each
+/// timespan.
+pub fn retime(text: &str, timespans: &[TimeSpan]) -> Option<String> {
+    let mut timespans = timespans.iter();
+    let mut result = String::with_capacity(text.len());
+    // A cue is an index line, a timing line and text lines up to the next blank line.
+    let mut in_cue = false;
+    let mut expect_timing = false;
+    for line in text.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\r', '\n']);
+        if expect_timing {
+            let caps = TIMING_LINE.captures(content)?;
+            let timespan = timespans.next()?;
+            result.push_str(&format!(
+                "{}{}{}{}{}",
+                &caps[1],
+                timestamp(timespan.start),
+                &caps[2],
+                timestamp(timespan.end),
+                &caps[3]
+            ));
+            result.push_str(&line[content.len()..]);
+            expect_timing = false;
+            continue;
+        }
+        if content.trim().is_empty() {
+            in_cue = false;
+        } else if !in_cue {
+            in_cue = true;
+            expect_timing = true;
+        }
+        result.push_str(line);
+    }
+    timespans.next().is_none().then_some(result)
+}
+
+/// Formats a time like subparse does in .srt files.
+fn timestamp(t: TimePoint) -> String {
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        t.hours(),
+        t.mins_comp(),
+        t.secs_comp(),
+        t.msecs_comp()
+    )
+}
+
+/// Removes all HTML style tags except the ones in `keep` (e.g. `["i", "b"]`).
+pub fn strip_tags(text: &str, keep: &[&str]) -> String {
+    HTML_TAG
+        .replace_all(text, |caps: &regex::Captures| {
+            if keep.contains(&caps[1].to_lowercase().as_str()) {
+                caps[0].to_lowercase()
+            } else {
+                String::new()
+            }
+        })
+        .to_string()
+}
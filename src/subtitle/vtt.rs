@@ -0,0 +1,352 @@
+use anyhow::Result as AnyResult;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::HashSet;
+use std::fmt::Write;
+use subparse::SubtitleEntry;
+use subparse::timetypes::{TimeDelta, TimePoint, TimeSpan};
+
+static TIMESTAMP_TAG: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"<((?:\d+:)?\d{2}:\d{2}\.\d{3})>").unwrap());
+
+/// A parsed WebVTT file. Everything except the cue timings and texts (the header, cue
+/// identifiers and settings, NOTE, STYLE and REGION blocks, and the blank lines between blocks)
+/// is kept as is when the file is written back.
+#[derive(Debug, Clone)]
+pub struct VttFile {
+    header: String,
+    /// The blocks after the header, each with the blank lines before it.
+    blocks: Vec<(String, VttBlock)>,
+}
+
+#[derive(Debug, Clone)]
+enum VttBlock {
+    /// NOTE, STYLE and REGION blocks (and anything else that isn't a cue).
+    Other(String),
+    Cue(VttCue),
+}
+
+#[derive(Debug, Clone)]
+struct VttCue {
+    id: Option<String>,
+    start: VttTimestamp,
+    /// The arrow between the timestamps with the whitespace around it.
+    arrow: String,
+    end: VttTimestamp,
+    /// The cue settings with the whitespace before and after them.
+    settings: String,
+    text: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct VttTimestamp {
+    time: TimePoint,
+    /// If the timestamp was written without hours (`mm:ss.ttt`).
+    short: bool,
+}
+
+impl VttFile {
+    pub fn parse(content: &str) -> AnyResult<VttFile> {
+        let content = content.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+        let mut blocks = split_blocks(&content).into_iter();
+
+        let (_, header) = blocks.next().unwrap_or_default();
+        if !starts_with_keyword(&header, "WEBVTT") {
+            bail!("invalid WebVTT file: missing WEBVTT header");
+        }
+
+        let blocks = blocks
+            .map(|(gap, block)| match parse_cue(&block) {
+                Some(cue) => (gap, VttBlock::Cue(cue)),
+                None => (gap, VttBlock::Other(block)),
+            })
+            .collect();
+
+        Ok(VttFile { header, blocks })
+    }
+
+    pub fn get_subtitle_entries(&self) -> Vec<SubtitleEntry> {
+        self.cues()
+            .map(|cue| SubtitleEntry {
+                timespan: TimeSpan::new(cue.start.time, cue.end.time),
+                line: Some(cue.text.clone()),
+            })
+            .collect()
+    }
+
+    pub fn update_subtitle_entries(&mut self, entries: &[SubtitleEntry]) -> AnyResult<()> {
+        let cues = self.cues_mut().collect::<Vec<_>>();
+        if cues.len() != entries.len() {
+            bail!(
+                "expected {} subtitle entries but got {}",
+                cues.len(),
+                entries.len()
+            );
+        }
+
+        for (cue, entry) in cues.into_iter().zip(entries) {
+            let old = TimeSpan::new(cue.start.time, cue.end.time);
+            if let Some(text) = &entry.line {
+                cue.text = text.clone();
+            }
+            cue.text = retime_timestamp_tags(&cue.text, old, entry.timespan);
+            cue.start.time = entry.timespan.start;
+            cue.end.time = entry.timespan.end;
+        }
+        Ok(())
+    }
+
+    /// Keeps only the cues at the given indices, in the given order. The kept cues are put in
+    /// the places of the cues at these indices, so the other blocks stay where they are.
+    pub fn select_cues(&mut self, indices: &[usize]) {
+        let mut cues = self
+            .blocks
+            .iter()
+            .filter_map(|(_, block)| match block {
+                VttBlock::Cue(cue) => Some(Some(cue.clone())),
+                VttBlock::Other(_) => None,
+            })
+            .collect::<Vec<_>>();
+        let kept = indices.iter().collect::<HashSet<_>>();
+        let mut order = indices.iter();
+        let mut cue_index = 0;
+        self.blocks = std::mem::take(&mut self.blocks)
+            .into_iter()
+            .filter_map(|(gap, block)| match block {
+                VttBlock::Cue(_) => {
+                    let keep = kept.contains(&cue_index);
+                    cue_index += 1;
+                    if keep {
+                        order
+                            .next()
+                            .and_then(|&i| cues[i].take())
+                            .map(|cue| (gap, VttBlock::Cue(cue)))
+                    } else {
+                        None
+                    }
+                }
+                other => Some((gap, other)),
+            })
+            .collect();
+    }
+
+    pub fn to_data(&self) -> Vec<u8> {
+        let mut out = self.header.clone();
+        out.push('\n');
+        for (gap, block) in &self.blocks {
+            out.push_str(gap);
+            match block {
+                VttBlock::Other(text) => out.push_str(text),
+                VttBlock::Cue(cue) => {
+                    if let Some(id) = &cue.id {
+                        out.push_str(id);
+                        out.push('\n');
+                    }
+                    out.push_str(&cue.start.to_string());
+                    out.push_str(&cue.arrow);
+                    out.push_str(&cue.end.to_string());
+                    out.push_str(&cue.settings);
+                    if !cue.text.is_empty() {
+                        out.push('\n');
+                        out.push_str(&cue.text);
+                    }
+                }
+            }
+            out.push('\n');
+        }
+        out.into_bytes()
+    }
+
+    fn cues(&self) -> impl Iterator<Item = &VttCue> {
+        self.blocks.iter().filter_map(|(_, block)| match block {
+            VttBlock::Cue(cue) => Some(cue),
+            VttBlock::Other(_) => None,
+        })
+    }
+
+    fn cues_mut(&mut self) -> impl Iterator<Item = &mut VttCue> {
+        self.blocks.iter_mut().filter_map(|(_, block)| match block {
+            VttBlock::Cue(cue) => Some(cue),
+            VttBlock::Other(_) => None,
+        })
+    }
+}
+
+impl std::fmt::Display for VttTimestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", timestamp(self.time, self.short))
+    }
+}
+
+/// Creates a .vtt file from the cues. The cue texts should use SRT markup; tags that WebVTT
+/// doesn't support are removed.
+pub fn write(cues: &[(TimeSpan, String)]) -> Vec<u8> {
+    let mut out = String::from("WEBVTT\n");
+    for (timespan, text) in cues {
+        let text = super::srt::strip_tags(text, &["i", "b", "u"]).replace('&', "&amp;");
+        let _ = write!(
+            out,
+            "\n{} --> {}\n{}\n",
+            timestamp(timespan.start, false),
+            timestamp(timespan.end, false),
+            text.trim_end()
+        );
+    }
+    out.into_bytes()
+}
+
+/// Converts WebVTT markup to SRT markup. Italic, bold and underline tags are kept, all other
+/// tags (including timestamp tags) are removed and character references are decoded.
+pub fn to_srt_markup(text: &str) -> String {
+    let text = super::srt::strip_tags(&TIMESTAMP_TAG.replace_all(text, ""), &["i", "b", "u"]);
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+/// Splits the content into blocks separated by one or more empty lines. Each block is returned
+/// with the empty lines before it (with their line breaks).
+fn split_blocks(content: &str) -> Vec<(String, String)> {
+    let mut blocks = Vec::new();
+    let mut gap = String::new();
+    let mut current: Vec<&str> = Vec::new();
+    for line in content.lines() {
+        if line.trim().is_empty() {
+            if !current.is_empty() {
+                blocks.push((std::mem::take(&mut gap), current.join("\n")));
+                current.clear();
+            }
+            gap.push_str(line);
+            gap.push('\n');
+        } else {
+            current.push(line);
+        }
+    }
+    if !current.is_empty() {
+        blocks.push((gap, current.join("\n")));
+    }
+    blocks
+}
+
+fn parse_cue(block: &str) -> Option<VttCue> {
+    if ["NOTE", "STYLE", "REGION"]
+        .iter()
+        .any(|keyword| starts_with_keyword(block, keyword))
+    {
+        return None;
+    }
+
+    let mut lines = block.splitn(2, '\n');
+    let first = lines.next()?;
+    let (id, timing_line, text) = if first.contains("-->") {
+        (None, first, lines.next().unwrap_or_default())
+    } else {
+        let mut rest = lines.next()?.splitn(2, '\n');
+        let timing_line = rest.next()?;
+        if !timing_line.contains("-->") {
+            return None;
+        }
+        (
+            Some(first.to_string()),
+            timing_line,
+            rest.next().unwrap_or_default(),
+        )
+    };
+
+    let (start, rest) = timing_line.split_once("-->")?;
+    let end_start = rest.len() - rest.trim_start().len();
+    let (end, settings) = match rest[end_start..].find(char::is_whitespace) {
+        Some(i) => rest.split_at(end_start + i),
+        None => (rest, ""),
+    };
+
+    Some(VttCue {
+        id,
+        start: parse_timestamp(start.trim())?,
+        arrow: format!(
+            "{}-->{}",
+            &start[start.trim_end().len()..],
+            &rest[..end_start]
+        ),
+        end: parse_timestamp(end.trim_start())?,
+        settings: settings.to_string(),
+        text: text.to_string(),
+    })
+}
+
+/// Returns true if the block starts with `keyword` followed by whitespace or nothing.
+fn starts_with_keyword(block: &str, keyword: &str) -> bool {
+    block
+        .strip_prefix(keyword)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+}
+
+fn parse_timestamp(s: &str) -> Option<VttTimestamp> {
+    let (clock, msecs) = s.split_once('.')?;
+    if msecs.len() != 3 {
+        return None;
+    }
+    let msecs = msecs.parse::<i64>().ok()?;
+
+    let parts = clock
+        .split(':')
+        .map(|part| part.parse::<i64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let (hours, mins, secs) = match parts[..] {
+        [mins, secs] => (0, mins, secs),
+        [hours, mins, secs] => (hours, mins, secs),
+        _ => return None,
+    };
+
+    Some(VttTimestamp {
+        time: TimePoint::from_components(hours, mins, secs, msecs),
+        short: parts.len() == 2,
+    })
+}
+
+/// Moves the timestamp tags (`<00:01:02.500>`) inside a cue text so that they keep their
+/// relative position inside the cue when the cue timespan changes from `old` to `new`.
+fn retime_timestamp_tags(text: &str, old: TimeSpan, new: TimeSpan) -> String {
+    TIMESTAMP_TAG
+        .replace_all(text, |caps: &regex::Captures| {
+            let Some(tag) = parse_timestamp(&caps[1]) else {
+                return caps[0].to_string();
+            };
+            let old_len = old.len().msecs();
+            let offset = tag.time.msecs() - old.start.msecs();
+            let new_offset = if old_len > 0 {
+                (offset as f64 * new.len().msecs() as f64 / old_len as f64).round() as i64
+            } else {
+                offset
+            };
+            let time = new.start + TimeDelta::from_msecs(new_offset);
+            format!("<{}>", timestamp(time, tag.short))
+        })
+        .to_string()
+}
+
+fn timestamp(t: TimePoint, short: bool) -> String {
+    // WebVTT has no negative timestamps.
+    let t = if t.msecs() < 0 {
+        TimePoint::from_msecs(0)
+    } else {
+        t
+    };
+    if short && t.hours() == 0 {
+        format!(
+            "{:02}:{:02}.{:03}",
+            t.mins_comp(),
+            t.secs_comp(),
+            t.msecs_comp()
+        )
+    } else {
+        format!(
+            "{:02}:{:02}:{:02}.{:03}",
+            t.hours(),
+            t.mins_comp(),
+            t.secs_comp(),
+            t.msecs_comp()
+        )
+    }
+}
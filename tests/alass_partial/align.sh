@@ -0,0 +1,4 @@
+#!/bin/sh
+# Fails for subs without "Hello", copies the others.
+grep -q Hello "$1" || { echo "no hello in $1" >&2; exit 3; }
+cp "$1" "$2"
@@ -0,0 +1,4 @@
+#!/bin/sh
+# Takes long for subs without "Hello", copies the others.
+grep -q Hello "$1" || sleep 30
+cp "$1" "$2"
@@ -1,6 +1,7 @@
 use assert_cmd::cargo_bin;
 use assert_cmd::prelude::*;
 use predicates::prelude::*;
+use std::path::Path;
 use std::process::Command;
 use std::thread;
 use std::time::Duration;
@@ -59,6 +60,91 @@ fn rename_does_not_happen_when_filter_is_not_matching() {
     assert!(files.contains(&"07_leading_sub.srt".to_string()));
 }
 
+#[test]
+fn flags_subs_that_do_not_match_the_duration_of_the_video() {
+    let dir = tempdir().unwrap();
+    util::copy("./tests/rename_duration", &dir).unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("-y")
+        .arg("--strict")
+        .arg("rename")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "show 02.srt -> ep02.mp4: the last cue ends 00:24:50.000 after the end of the video",
+        ))
+        .stderr(predicate::str::contains("show 01.srt").not());
+    assert!(dir.path().join("show 02.srt").exists());
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("-y")
+        .arg("rename")
+        .arg("--duration-tolerance")
+        .arg("30m")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("warning").not());
+
+    let files = util::files_in(&dir);
+    assert!(files.contains(&"ep01.srt".to_string()));
+    assert!(files.contains(&"ep02.srt".to_string()));
+
+    // Subs that can't be read don't pass the check, and are read with --encoding.
+    let dir = tempdir().unwrap();
+    util::copy("./tests/rename_duration", &dir).unwrap();
+    std::fs::write(dir.path().join("show 02.srt"), b"\xff\xfe\x00garbage").unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("-y")
+        .arg("--strict")
+        .arg("rename")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "show 02.srt -> ep02.mp4: the subtitle can't be read to check its duration",
+        ));
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("-y")
+        .arg("--strict")
+        .arg("rename")
+        .arg("--encoding")
+        .arg("UTF-16LE")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "show 01.srt -> ep01.mkv: the subtitle can't be read to check its duration: the \
+             content is not valid UTF-16LE",
+        ));
+
+    // Formats that sub-batch can't read aren't checked.
+    let dir = tempdir().unwrap();
+    util::copy("./tests/rename_duration", &dir).unwrap();
+    std::fs::remove_file(dir.path().join("show 02.srt")).unwrap();
+    std::fs::write(
+        dir.path().join("show 02.smi"),
+        "<SAMI><BODY><SYNC Start=3000000><P>Hello</SAMI>",
+    )
+    .unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("-y")
+        .arg("--strict")
+        .arg("rename")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("show 02.smi").not());
+
+    let files = util::files_in(&dir);
+    assert!(files.contains(&"ep02.smi".to_string()));
+}
+
 #[test]
 fn can_rename_sub_file_that_contains_invalid_utf8() {
     let dir = tempdir().unwrap();
@@ -162,7 +248,7 @@ fn can_change_timings_of_sub_files() {
         .success();
 
     let first = files.iter().find(|f| f.contains("sub.srt")).unwrap();
-    let first_text = std::fs::read_to_string(&dir.path().join(first)).unwrap();
+    let first_text = std::fs::read_to_string(dir.path().join(first)).unwrap();
 
     let first_t = timings(&first_text);
     assert_eq!(first_t[0].0, "00:02:33,100");
@@ -176,13 +262,288 @@ fn can_change_timings_of_sub_files() {
         .success();
 
     let second = files.iter().find(|f| f.contains("another.srt")).unwrap();
-    let second_text = std::fs::read_to_string(&dir.path().join(second)).unwrap();
+    let second_text = std::fs::read_to_string(dir.path().join(second)).unwrap();
 
     let second_t = timings(&second_text);
     assert_eq!(second_t[0].0, "00:12:33,488");
     assert_eq!(second_t[1].1, "00:12:40,161");
 }
 
+#[test]
+fn can_change_timings_using_time_expressions() {
+    let dir = tempdir().unwrap();
+    util::copy("./tests/time_subs_only", &dir).unwrap();
+
+    for time in ["1m2.5s", "-00:01:02,400", "-4f"] {
+        Command::new(cargo_bin!())
+            .current_dir(&dir)
+            .arg("--filter-sub")
+            .arg("^sub")
+            .arg("time")
+            .arg(time)
+            .assert()
+            .success();
+    }
+
+    let text = std::fs::read_to_string(dir.path().join("sub.srt")).unwrap();
+    let t = timings(&text);
+    assert_eq!(t[0].0, "00:02:32,940");
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("time")
+        .arg("1m2x")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("\"2x\""));
+}
+
+#[test]
+fn can_change_timings_of_webvtt_files() {
+    let dir = tempdir().unwrap();
+    util::copy("./tests/time_vtt", &dir).unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("time")
+        .arg("1500")
+        .assert()
+        .success();
+
+    let text = std::fs::read_to_string(dir.path().join("sub.vtt")).unwrap();
+    let t = timings(&text);
+    assert_eq!(t[0].0, "00:02.500");
+    assert_eq!(t[0].1, "00:05.500 align:start position:10%");
+    assert_eq!(t[1].0, "00:00:07.000");
+    assert!(text.contains("<v Roger>Hello <00:03.500>there"));
+    assert!(text.contains("NOTE This is a comment\nthat spans two lines"));
+    assert!(text.contains("STYLE\n::cue {"));
+
+    let text = std::fs::read_to_string(dir.path().join("sub.srt")).unwrap();
+    let t = timings(&text);
+    assert_eq!(t[0].0, "00:02:34,500");
+}
+
+#[test]
+fn skips_unsupported_subs_unless_strict() {
+    let dir = tempdir().unwrap();
+    util::copy("./tests/time_mixed", &dir).unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("--strict")
+        .arg("time")
+        .arg("100")
+        .assert()
+        .code(1);
+
+    let text = std::fs::read_to_string(dir.path().join("sub.srt")).unwrap();
+    assert_eq!(timings(&text)[0].0, "00:02:33,000");
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("time")
+        .arg("100")
+        .assert()
+        .code(2)
+        .stdout(predicate::str::contains(
+            "sub.ttml: unsupported subtitle format (.ttml)",
+        ));
+
+    let text = std::fs::read_to_string(dir.path().join("sub.srt")).unwrap();
+    assert_eq!(timings(&text)[0].0, "00:02:33,100");
+}
+
+#[test]
+fn detects_encoding_and_can_change_it() {
+    let dir = tempdir().unwrap();
+    util::copy("./tests/time_cp1250", &dir).unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("--verbose")
+        .arg("time")
+        .arg("100")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("sub.srt: windows-1250 (guessed)"));
+
+    let data = std::fs::read(dir.path().join("sub.srt")).unwrap();
+    assert!(std::str::from_utf8(&data).is_err());
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("time")
+        .arg("--output-encoding")
+        .arg("utf-8")
+        .arg("0")
+        .assert()
+        .success();
+
+    let text = std::fs::read_to_string(dir.path().join("sub.srt")).unwrap();
+    assert!(text.contains("Příliš žluťoučký kůň"));
+    assert_eq!(timings(&text)[0].0, "00:00:01,100");
+}
+
+#[test]
+fn reads_subs_with_a_bom_in_the_encoding_of_the_bom() {
+    let dir = tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("sub.srt"),
+        "\u{feff}1\n00:00:01,000 --> 00:00:02,000\nCafé\n",
+    )
+    .unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("--verbose")
+        .arg("time")
+        .arg("--encoding")
+        .arg("windows-1252")
+        .arg("100")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "sub.srt: UTF-8 (detected from BOM)",
+        ));
+
+    let data = std::fs::read(dir.path().join("sub.srt")).unwrap();
+    assert_eq!(
+        std::str::from_utf8(&data).unwrap(),
+        "\u{feff}1\n00:00:01,100 --> 00:00:02,100\nCafé\n"
+    );
+}
+
+#[test]
+fn preserves_bom_and_line_endings_when_changing_timings() {
+    let dir = tempdir().unwrap();
+    util::copy("./tests/time_subs_only", &dir).unwrap();
+    let original = std::fs::read("./tests/time_subs_only/sub.srt").unwrap();
+
+    for time in ["100", "-100"] {
+        Command::new(cargo_bin!())
+            .current_dir(&dir)
+            .arg("--filter-sub")
+            .arg("^sub")
+            .arg("time")
+            .arg(time)
+            .assert()
+            .success();
+    }
+
+    let data = std::fs::read(dir.path().join("sub.srt")).unwrap();
+    assert_eq!(data, original);
+}
+
+#[test]
+fn only_changes_the_timings_of_srt_and_vtt_files() {
+    let dir = tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("sub.srt"),
+        "\u{feff}1\r\n00:00:01,000 --> 00:00:02,000  \r\n<i>Hello</i>  \r\n\r\n\r\n\
+         2\r\n00:00:03,000-->00:00:04,000\r\nWorld\r\n\r\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("sub.vtt"),
+        "WEBVTT\n\n\n00:00:01.000 --> 00:00:02.000  \nHello  \n\n \n\n\
+         id\n00:03.000-->00:04.000 align:start \nWorld\n",
+    )
+    .unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("time")
+        .arg("1000")
+        .assert()
+        .success();
+
+    let data = std::fs::read(dir.path().join("sub.srt")).unwrap();
+    assert_eq!(
+        std::str::from_utf8(&data).unwrap(),
+        "\u{feff}1\r\n00:00:02,000 --> 00:00:03,000  \r\n<i>Hello</i>  \r\n\r\n\r\n\
+         2\r\n00:00:04,000-->00:00:05,000\r\nWorld\r\n\r\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("sub.vtt")).unwrap(),
+        "WEBVTT\n\n\n00:00:02.000 --> 00:00:03.000  \nHello  \n\n \n\n\
+         id\n00:04.000-->00:05.000 align:start \nWorld\n"
+    );
+}
+
+#[test]
+fn keeps_backups_of_changed_subs() {
+    let dir = tempdir().unwrap();
+    util::copy("./tests/time_subs_only", &dir).unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("--backup=.orig")
+        .arg("time")
+        .arg("100")
+        .assert()
+        .success();
+
+    let text = std::fs::read_to_string(dir.path().join("sub.srt")).unwrap();
+    assert_eq!(timings(&text)[0].0, "00:02:33,100");
+    let backup = std::fs::read_to_string(dir.path().join("sub.srt.orig")).unwrap();
+    assert_eq!(timings(&backup)[0].0, "00:02:33,000");
+    assert_eq!(util::files_in(&dir).len(), 4);
+}
+
+#[test]
+fn can_write_changed_subs_to_out_dir() {
+    let dir = tempdir().unwrap();
+    let out_dir = tempdir().unwrap();
+    util::copy("./tests/time_subs_only", &dir).unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("--out-dir")
+        .arg(out_dir.path().join("out"))
+        .arg("time")
+        .arg("100")
+        .assert()
+        .success();
+
+    let text = std::fs::read_to_string(dir.path().join("sub.srt")).unwrap();
+    assert_eq!(timings(&text)[0].0, "00:02:33,000");
+    let text = std::fs::read_to_string(out_dir.path().join("out/sub.srt")).unwrap();
+    assert_eq!(timings(&text)[0].0, "00:02:33,100");
+    assert_eq!(util::files_in(out_dir.path().join("out")).len(), 2);
+}
+
+#[test]
+fn uses_frame_rate_of_video_for_microdvd_subs() {
+    let dir = tempdir().unwrap();
+    util::copy("./tests/time_video_fps", &dir).unwrap();
+
+    // ep01.mp4 is 23.976 fps.
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("time")
+        .arg("1s")
+        .assert()
+        .success();
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("ep01.sub")).unwrap(),
+        "{48}{73}Hello\n{123}{173}World\n"
+    );
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("time")
+        .arg("--fps")
+        .arg("25")
+        .arg("1s")
+        .assert()
+        .success();
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("ep01.sub")).unwrap(),
+        "{73}{98}Hello\n{148}{198}World\n"
+    );
+}
+
 #[test]
 fn timings_do_not_change_when_filter_is_not_matching() {
     let dir = tempdir().unwrap();
@@ -199,7 +560,7 @@ fn timings_do_not_change_when_filter_is_not_matching() {
         .failure();
 
     let first = files.iter().find(|f| f.contains("sub.srt")).unwrap();
-    let first_text = std::fs::read_to_string(&dir.path().join(first)).unwrap();
+    let first_text = std::fs::read_to_string(dir.path().join(first)).unwrap();
 
     let first_t = timings(&first_text);
     assert_eq!(first_t[0].0, "00:02:33,000");
@@ -207,6 +568,575 @@ fn timings_do_not_change_when_filter_is_not_matching() {
 }
 
 #[test]
+fn can_stretch_timings_of_sub_files() {
+    let dir = tempdir().unwrap();
+    util::copy("./tests/time_subs_only", &dir).unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("--filter-sub")
+        .arg("^sub")
+        .arg("time")
+        .arg("--stretch")
+        .arg("1.5")
+        .arg("--anchor")
+        .arg("153000")
+        .assert()
+        .success();
+
+    let text = std::fs::read_to_string(dir.path().join("sub.srt")).unwrap();
+    let t = timings(&text);
+    assert_eq!(t[0].0, "00:02:33,000");
+    assert_eq!(t[0].1, "00:02:39,263");
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("--filter-sub")
+        .arg("^another")
+        .arg("time")
+        .arg("--fps-from")
+        .arg("25")
+        .arg("--fps-to")
+        .arg("23.976")
+        .assert()
+        .success();
+
+    let text = std::fs::read_to_string(dir.path().join("another.srt")).unwrap();
+    let t = timings(&text);
+    assert_eq!(t[0].0, "00:13:05,669");
+}
+
+#[test]
+fn can_sync_sub_files_using_two_cues() {
+    let dir = tempdir().unwrap();
+    util::copy("./tests/time_subs_only", &dir).unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("--filter-sub")
+        .arg("^sub")
+        .arg("sync")
+        .arg("--first")
+        .arg("1")
+        .arg("00:02:34.000")
+        .arg("--second")
+        .arg("誕生日")
+        .arg("00:02:42,2")
+        .assert()
+        .success();
+
+    let text = std::fs::read_to_string(dir.path().join("sub.srt")).unwrap();
+    let t = timings(&text);
+    assert_eq!(t[0].0, "00:02:34,000");
+    assert_eq!(t[1].0, "00:02:38,822");
+    assert_eq!(t[2].0, "00:02:42,200");
+}
+
+#[test]
+fn can_convert_sub_files_to_another_format() {
+    let dir = tempdir().unwrap();
+    util::copy("./tests/time_subs_only", &dir).unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("convert")
+        .arg("--to")
+        .arg("ass")
+        .arg("--replace")
+        .assert()
+        .success();
+
+    let files = util::files_in(&dir);
+    assert_eq!(files.len(), 2);
+    assert!(files.contains(&"sub.ass".to_string()));
+    assert!(files.contains(&"another.ass".to_string()));
+
+    let text = std::fs::read_to_string(dir.path().join("sub.ass")).unwrap();
+    assert!(
+        text.contains("Dialogue: 0,0:02:33.00,0:02:37.17,Default,,0,0,0,,いや ですから 私に\\N")
+    );
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("convert")
+        .arg("--to")
+        .arg("srt")
+        .assert()
+        .success();
+
+    let text = std::fs::read_to_string(dir.path().join("sub.srt")).unwrap();
+    let t = timings(&text);
+    assert_eq!(t[0].0, "00:02:33,000");
+    assert_eq!(t[2].1, "00:02:44,650");
+}
+
+#[test]
+fn refuses_to_convert_subs_with_the_same_name_to_one_file() {
+    let dir = tempdir().unwrap();
+    util::copy("./tests/time_subs_only", &dir).unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("convert")
+        .arg("--to")
+        .arg("ass")
+        .assert()
+        .success();
+
+    // sub.srt and sub.ass would both be converted to sub.vtt.
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("convert")
+        .arg("--to")
+        .arg("vtt")
+        .arg("--replace")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "more than one subtitle would be converted to",
+        ));
+
+    let files = util::files_in(&dir);
+    assert_eq!(files.len(), 4);
+    assert!(!files.iter().any(|file| file.ends_with(".vtt")));
+}
+
+#[test]
+fn can_clean_sub_files() {
+    let dir = tempdir().unwrap();
+    util::copy("./tests/clean", &dir).unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("clean")
+        .arg("--pattern")
+        .arg(", see you")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "sub.srt: 2 cues changed, 2 cues removed",
+        ));
+
+    let text = std::fs::read_to_string(dir.path().join("sub.srt")).unwrap();
+    assert_eq!(
+        text,
+        "1\n00:00:03,000 --> 00:00:04,000\n- Hi there.\n- Hello!\n\n\
+         2\n00:00:07,000 --> 00:00:08,000\nGoodbye.\n"
+    );
+
+    // A clean sub isn't written again.
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("--backup=.bak")
+        .arg("clean")
+        .arg("--pattern")
+        .arg(", see you")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("sub.srt: unchanged"));
+    assert!(!dir.path().join("sub.srt.bak").exists());
+}
+
+#[test]
+fn can_remove_cues_from_ssa_and_microdvd_files() {
+    let dir = tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("sub.ass"),
+        "[Events]\n\
+         Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+         Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,[DOOR SLAMS]\n\
+         Comment: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,[DOOR SLAMS]\n\
+         Dialogue: 0,0:00:03.00,0:00:04.00,Default,,0,0,0,,JOHN: Hi there.\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("sub.sub"),
+        "{25}{50}[DOOR SLAMS]\n{75}{100}{Y:i}(laughs)|JOHN: Hi there.\n{125}{150}Bye\n",
+    )
+    .unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("clean")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "sub.ass: 1 cues changed, 1 cues removed",
+        ))
+        .stdout(predicate::str::contains(
+            "sub.sub: 1 cues changed, 2 cues removed",
+        ));
+
+    let text = std::fs::read_to_string(dir.path().join("sub.ass")).unwrap();
+    assert_eq!(
+        text,
+        "[Events]\n\
+         Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+         Comment: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,[DOOR SLAMS]\n\
+         Dialogue: 0,0:00:03.00,0:00:04.00,Default,,0,0,0,,Hi there.\n"
+    );
+    let text = std::fs::read_to_string(dir.path().join("sub.sub")).unwrap();
+    assert_eq!(text, "{75}{100}{y:i}Hi there.\n{125}{150}Bye\n");
+}
+
+#[test]
+fn can_merge_subs_into_bilingual_sub() {
+    let dir = tempdir().unwrap();
+    util::copy("./tests/merge", &dir).unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("merge")
+        .arg("--top")
+        .arg("en")
+        .arg("--bottom")
+        .arg("jp")
+        .arg("--format")
+        .arg("srt")
+        .arg("--name")
+        .arg("{video}.{ext}")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "ep01.en.srt + ep01.jp.srt -> ep01.srt",
+        ));
+
+    let text = std::fs::read_to_string(dir.path().join("ep01.srt")).unwrap();
+    assert!(text.contains("00:00:01,000 --> 00:00:03,100\nHello\nこんにちは\n"));
+    assert_eq!(timings(&text).len(), 3);
+}
+
+#[test]
+fn can_show_info_about_subs() {
+    let dir = tempdir().unwrap();
+    util::copy("./tests/merge", &dir).unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("info")
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"ep01\.jp\.srt +SubRip +UTF-8 +2 +00:00:01\.200 +00:00:09\.000 +00:00:07\.800 +0 +jp").unwrap());
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("info")
+        .arg("--json")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            r#"{"file":"ep01.en.srt","format":"SubRip","encoding":"UTF-8","cues":2,"first_ms":1000,"last_ms":6000,"span_ms":5000,"overlapping":0,"secondary_ext":"en"}"#,
+        ));
+}
+
+#[test]
+fn lint_reports_problems_and_fails() {
+    let dir = tempdir().unwrap();
+    util::copy("./tests/lint", &dir).unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("lint")
+        .arg("--video-length")
+        .arg("5s")
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains(
+            "sub.srt:2: 00:00:02.500: overlaps cue 1 by 500ms",
+        ))
+        .stdout(predicate::str::contains(
+            "sub.srt:2: 00:00:02.500: line 1 has 59 characters (max 42)",
+        ))
+        .stdout(predicate::str::contains(
+            "sub.srt:3: 00:00:06.000: has a duration of 0ms",
+        ))
+        .stdout(predicate::str::contains(
+            "sub.srt:4: 00:00:05.000: starts before the previous cue",
+        ))
+        .stdout(predicate::str::contains(
+            "sub.srt:4: 00:00:05.000: ends 500ms after the end of the video",
+        ));
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("lint")
+        .arg("--max-cps")
+        .arg("100")
+        .arg("--max-line-length")
+        .arg("80")
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("characters").not());
+}
+
+#[test]
+fn lint_checks_subs_against_the_duration_of_their_video() {
+    let dir = tempdir().unwrap();
+    util::copy("./tests/diagnose", &dir).unwrap();
+    // The video is 10s long.
+    std::fs::write(
+        dir.path().join("ep01.srt"),
+        "1\n00:00:01,000 --> 00:00:03,000\nHello\n\n2\n00:00:09,000 --> 00:00:10,500\nBye\n",
+    )
+    .unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("lint")
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains(
+            "ep01.srt:2: 00:00:09.000: ends 500ms after the end of the video",
+        ));
+
+    // --video-length overrides the duration of the video.
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("lint")
+        .arg("--video-length")
+        .arg("11s")
+        .assert()
+        .success();
+}
+
+#[test]
+fn can_fix_timing_problems() {
+    let dir = tempdir().unwrap();
+    util::copy("./tests/fix", &dir).unwrap();
+    let original = std::fs::read_to_string(dir.path().join("sub.srt")).unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("fix")
+        .arg("--dry-run")
+        .arg("--min-gap")
+        .arg("100ms")
+        .arg("--min-duration")
+        .arg("1s")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "sub.srt:1: 00:00:05.000: moved to position 4",
+        ))
+        .stdout(predicate::str::contains(
+            "sub.srt:4: 00:00:03.000: merged into cue 3",
+        ))
+        .stdout(predicate::str::contains(
+            "sub.srt:2: -00:00:00.500: start -00:00:00.500 -> 00:00:00.000",
+        ))
+        .stdout(predicate::str::contains(
+            "sub.srt:1: 00:00:05.000: end 00:00:07.000 -> 00:00:06.400 (overlaps cue 5)",
+        ))
+        .stdout(predicate::str::contains(
+            "sub.srt:5: 00:00:06.500: end 00:00:06.600 -> 00:00:07.500 (shorter than 1000ms)",
+        ));
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("sub.srt")).unwrap(),
+        original
+    );
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("fix")
+        .arg("--min-gap")
+        .arg("100ms")
+        .arg("--min-duration")
+        .arg("1s")
+        .assert()
+        .success();
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("sub.srt")).unwrap(),
+        "1\n00:00:00,000 --> 00:00:00,900\nFirst.\n\n2\n00:00:01,000 --> 00:00:04,000\nSecond.\n\n\
+         3\n00:00:05,000 --> 00:00:06,400\nThird.\n\n4\n00:00:06,500 --> 00:00:07,500\nFourth.\n"
+    );
+}
+
+#[test]
+fn detects_subs_made_for_another_frame_rate() {
+    let dir = tempdir().unwrap();
+    util::copy("./tests/diagnose", &dir).unwrap();
+    // The cues of ep02 and ep03 span 0.5% and 2.5% less than their video, which is normal for
+    // correctly timed subs. extra.srt has no video.
+    for (name, end) in [("ep02", "00:00:09,950"), ("ep03", "00:00:09,750")] {
+        std::fs::copy(
+            dir.path().join("ep01.mp4"),
+            dir.path().join(format!("{}.mp4", name)),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join(format!("{}.srt", name)),
+            format!(
+                "1\n00:00:00,000 --> 00:00:03,000\nHello\n\n2\n00:00:07,000 --> {}\nBye\n",
+                end
+            ),
+        )
+        .unwrap();
+    }
+    std::fs::copy(dir.path().join("ep01.srt"), dir.path().join("extra.srt")).unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("-y")
+        .arg("diagnose")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "ep01.srt -> ep01.mp4: cues span 00:00:09.590, video is 00:00:10.000, ratio 0.9590: \
+             made for 25 fps, stretch by 1.04271",
+        ))
+        .stdout(
+            predicate::str::contains("ep02.srt -> ep02.mp4")
+                .and(predicate::str::contains("ratio 0.9950: ok")),
+        )
+        .stdout(predicate::str::contains("ratio 0.9750: ok"))
+        .stdout(predicate::str::contains(
+            "1 of 3 subtitle file(s) look like they were made for 25 fps instead of 23.976 fps",
+        ));
+    // Without --apply nothing is changed when the confirmation is skipped.
+    let srt = std::fs::read_to_string(dir.path().join("ep01.srt")).unwrap();
+    assert!(srt.contains("00:00:07,000 --> 00:00:09,590"));
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("diagnose")
+        .arg("--apply")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("stretched 1 subtitle file(s)"));
+    let srt = std::fs::read_to_string(dir.path().join("ep01.srt")).unwrap();
+    assert!(srt.contains("00:00:00,000 --> 00:00:03,128"));
+    assert!(srt.contains("00:00:07,299 --> 00:00:10,000"));
+    // Only the flagged sub is stretched.
+    for (name, end) in [("ep02", "09,950"), ("ep03", "09,750"), ("extra", "09,590")] {
+        let srt = std::fs::read_to_string(dir.path().join(format!("{}.srt", name))).unwrap();
+        assert!(srt.contains(&format!("00:00:07,000 --> 00:00:{}", end)));
+    }
+
+    // Cues that span 0.1% less than the video were made for 24 fps, and it is the span that
+    // counts, not the end of the last cue.
+    let dir = tempdir().unwrap();
+    util::copy("./tests/diagnose", &dir).unwrap();
+    std::fs::write(
+        dir.path().join("ep01.srt"),
+        "1\n00:00:00,005 --> 00:00:03,000\nHello\n\n2\n00:00:07,000 --> 00:00:09,995\nBye\n",
+    )
+    .unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("-y")
+        .arg("diagnose")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "cues span 00:00:09.990, video is 00:00:10.000, ratio 0.9990: made for 24 fps, \
+             stretch by 1.00100",
+        ))
+        .stdout(predicate::str::contains(
+            "1 of 1 subtitle file(s) look like they were made for 24 fps instead of 23.976 fps",
+        ));
+}
+
+#[test]
+fn can_sync_subs_to_a_reference_subtitle() {
+    let dir = tempdir().unwrap();
+    util::copy("./tests/ref_sync", &dir).unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("ref-sync")
+        .arg("--ref-ext")
+        .arg("en")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "ep01.de.srt: offset -2000ms for cues 1-10, offset -5000ms for cues 11-20",
+        ));
+    let reference = std::fs::read_to_string(dir.path().join("ep01.en.srt")).unwrap();
+    let synced = std::fs::read_to_string(dir.path().join("ep01.de.srt")).unwrap();
+    assert_eq!(timings(&synced), timings(&reference));
+
+    // The reference can also be a subtitle track of the video.
+    let dir = tempdir().unwrap();
+    util::copy("./tests/ref_sync_track", &dir).unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("ref-sync")
+        .arg("--ref-track")
+        .arg("eng")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ep01.de.srt: offset -1000ms"));
+    let synced = std::fs::read_to_string(dir.path().join("ep01.de.srt")).unwrap();
+    assert!(synced.contains("00:00:01,000 --> 00:00:03,000"));
+    assert!(synced.contains("00:00:05,500 --> 00:00:07,000"));
+
+    // A sub that is already in sync isn't written again.
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("--backup=.bak")
+        .arg("ref-sync")
+        .arg("--ref-track")
+        .arg("eng")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "ep01.de.srt: offset 0ms, unchanged",
+        ));
+    assert!(!dir.path().join("ep01.de.srt.bak").exists());
+}
+
+#[test]
+fn can_extract_subs_from_mkv() {
+    let dir = tempdir().unwrap();
+    util::copy("./tests/extract", &dir).unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("extract")
+        .arg("--list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "ep01.mkv: track 2: eng, S_TEXT/UTF8 (srt), \"English\", default",
+        ))
+        .stdout(predicate::str::contains(
+            "ep01.mkv: track 4: eng, S_HDMV/PGS (can't be extracted), \"Signs\", forced",
+        ));
+    assert!(!dir.path().join("ep01.eng.srt").exists());
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("extract")
+        .assert()
+        .success();
+
+    let srt = std::fs::read_to_string(dir.path().join("ep01.eng.srt")).unwrap();
+    assert_eq!(
+        srt,
+        "1\n00:00:01,000 --> 00:00:03,000\nHello,\nworld!\n\n\
+         2\n00:00:05,500 --> 00:00:07,000\n<i>Bye.</i>\n\n"
+    );
+    let ass = std::fs::read_to_string(dir.path().join("ep01.jpn.ass")).unwrap();
+    assert!(ass.starts_with("[Script Info]"));
+    assert!(ass.ends_with(
+        "Dialogue: 0,0:00:01.00,0:00:01.50,Default,Bob,0,0,0,,{\\i1}Hi{\\i0}\n\
+         Dialogue: 0,0:00:01.50,0:00:03.00,Default,,0,0,0,,こんにちは\n\
+         Dialogue: 0,0:00:05.50,0:00:07.00,Default,,0,0,0,,さようなら\n"
+    ));
+
+    // The extracted subs are never overwritten.
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("extract")
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("already exists"));
+}
+
+#[test]
+#[allow(clippy::suspicious_command_arg_space)]
 fn can_run_alass_on_sub_file() {
     let dir = tempdir().unwrap();
     util::copy("./tests/dummy", &dir).unwrap();
@@ -221,6 +1151,249 @@ fn can_run_alass_on_sub_file() {
         .stdout(predicate::str::contains("shifted block of 1"));
 }
 
+#[test]
+#[cfg(unix)]
+fn can_align_subs_with_a_command_template() {
+    let dir = tempdir().unwrap();
+    let out_dir = tempdir().unwrap();
+    util::copy("./tests/dummy", &dir).unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("-y")
+        .arg("--out-dir")
+        .arg(out_dir.path())
+        .arg("alass")
+        .arg("--command")
+        .arg("cp {sub} {out}")
+        .assert()
+        .success();
+    assert_eq!(
+        std::fs::read(out_dir.path().join("sample-video-01.srt")).unwrap(),
+        std::fs::read(dir.path().join("sample-video-01.srt")).unwrap()
+    );
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("-y")
+        .arg("alass")
+        .arg("--command")
+        .arg("false {sub} {out}")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("false failed"))
+        .stderr(predicate::str::contains("all 1 alignments failed"));
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("-y")
+        .arg("alass")
+        .arg("--command")
+        .arg("cp {sub}")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--command must contain {out}"));
+}
+
+#[test]
+#[cfg(unix)]
+fn aligns_all_subs_when_some_alignments_fail() {
+    let dir = tempdir().unwrap();
+    util::copy("./tests/alass_partial", &dir).unwrap();
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("-y")
+        .arg("alass")
+        .arg("--command")
+        .arg("sh align.sh {sub} {out}")
+        .arg("--nopar")
+        .assert()
+        .code(2)
+        .stdout(predicate::str::is_match("ok +0 +ep01.srt +ep01.mkv").unwrap())
+        .stdout(predicate::str::is_match("failed +3 +ep02.srt +ep02.mkv .* sh failed").unwrap())
+        .stderr(predicate::str::contains(
+            "processed 1 subtitle file(s) but 1 failed",
+        ));
+
+    let log = std::fs::read_to_string(dir.path().join(".sub-batch-logs/ep02.srt.log")).unwrap();
+    assert!(log.contains("no hello in"));
+    assert!(dir.path().join(".sub-batch-logs/ep01.srt.log").exists());
+    let sub = std::fs::read_to_string(dir.path().join("ep02.srt")).unwrap();
+    assert!(sub.contains("Bye"));
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("-y")
+        .arg("--strict")
+        .arg("alass")
+        .arg("--command")
+        .arg("sh align.sh {sub} {out}")
+        .arg("--force")
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("1 of 2 alignments failed"));
+}
+
+#[test]
+#[cfg(unix)]
+fn stops_alignments_that_take_too_long() {
+    let dir = tempdir().unwrap();
+    util::copy("./tests/alass_partial", &dir).unwrap();
+
+    let start = std::time::Instant::now();
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("-y")
+        .arg("alass")
+        .arg("--command")
+        .arg("sh slow.sh {sub} {out}")
+        .arg("--jobs")
+        .arg("2")
+        .arg("--timeout")
+        .arg("1s")
+        .assert()
+        .code(2)
+        .stdout(predicate::str::is_match("ok +0 +ep01.srt").unwrap())
+        .stdout(predicate::str::contains("sh timed out after 00:00:01.000"));
+    assert!(start.elapsed() < Duration::from_secs(20));
+
+    let sub = std::fs::read_to_string(dir.path().join("ep02.srt")).unwrap();
+    assert!(sub.contains("Bye"));
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .arg("alass")
+        .arg("--jobs")
+        .arg("0")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid --jobs value: 0"));
+}
+
+#[test]
+#[cfg(unix)]
+fn can_write_aligned_subs_with_a_suffix() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempdir().unwrap();
+    util::copy("./tests/alass_partial", &dir).unwrap();
+
+    // A stand-in for alass that reports a split and copies the sub.
+    let bin = tempdir().unwrap();
+    let alass = bin.path().join("alass-cli");
+    std::fs::write(
+        &alass,
+        "#!/bin/sh\n\
+        echo 'shifted block of 1 subtitles with length 0:00:01.000 by -0:00:01.500'\n\
+        echo 'shifted block of 2 subtitles with length 0:00:03.000 by 0:00:02.000'\n\
+        cp \"$2\" \"$3\"\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&alass, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!(
+        "{}:{}",
+        bin.path().display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    Command::new(cargo_bin!())
+        .current_dir(&dir)
+        .env("PATH", path)
+        .arg("-y")
+        .arg("alass")
+        .arg("--suffix")
+        .arg(".synced")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::is_match(
+                "ok +0 +ep01.srt +ep01.mkv +offset -1.500s for 1 cues, offset 2.000s for 2 cues",
+            )
+            .unwrap(),
+        );
+
+    let original = std::fs::read(dir.path().join("ep01.srt")).unwrap();
+    let aligned = std::fs::read(dir.path().join("ep01.synced.srt")).unwrap();
+    assert_eq!(original, aligned);
+    assert!(dir.path().join("ep02.synced.srt").exists());
+}
+
+#[test]
+#[cfg(unix)]
+fn skips_pairs_that_are_unchanged_since_their_last_alignment() {
+    let dir = tempdir().unwrap();
+    util::copy("./tests/alass_partial", &dir).unwrap();
+    let align = |extra: &[&str]| {
+        Command::new(cargo_bin!())
+            .current_dir(&dir)
+            .arg("-y")
+            .arg("alass")
+            .arg("--command")
+            .arg("cp {sub} {out}")
+            .args(extra)
+            .assert()
+            .success()
+    };
+
+    align(&[])
+        .stdout(predicate::str::is_match("ok +0 +ep01.srt").unwrap())
+        .stdout(predicate::str::is_match("ok +0 +ep02.srt").unwrap());
+    assert!(dir.path().join(".sub-batch-alignments").exists());
+
+    align(&[])
+        .stdout(predicate::str::is_match("unchanged +- +ep01.srt").unwrap())
+        .stdout(predicate::str::is_match("unchanged +- +ep02.srt").unwrap())
+        .stdout(predicate::str::contains(
+            "2 pair(s) are unchanged since their last alignment",
+        ));
+
+    let sub = dir.path().join("ep01.srt");
+    let content = std::fs::read_to_string(&sub).unwrap();
+    std::fs::write(&sub, content.replace("Hello", "Hi")).unwrap();
+    align(&[])
+        .stdout(predicate::str::is_match("ok +0 +ep01.srt").unwrap())
+        .stdout(predicate::str::is_match("unchanged +- +ep02.srt").unwrap());
+
+    align(&["--force"])
+        .stdout(predicate::str::is_match("ok +0 +ep01.srt").unwrap())
+        .stdout(predicate::str::is_match("ok +0 +ep02.srt").unwrap());
+}
+
+#[test]
+#[cfg(unix)]
+fn can_align_subs_in_a_relative_path() {
+    let dir = tempdir().unwrap();
+    util::copy("./tests/alass_partial", dir.path().join("season")).unwrap();
+    let align = |path: &Path| {
+        Command::new(cargo_bin!())
+            .current_dir(&dir)
+            .arg("-y")
+            .arg("--path")
+            .arg(path)
+            .arg("alass")
+            .arg("--command")
+            .arg("sh align.sh {sub} {out}")
+            .assert()
+    };
+
+    align(Path::new("season"))
+        .code(2)
+        .stdout(predicate::str::is_match("ok +0 +ep01.srt").unwrap());
+    assert!(!dir.path().join("season/season").exists());
+    let log =
+        std::fs::read_to_string(dir.path().join("season/.sub-batch-logs/ep02.srt.log")).unwrap();
+    assert!(log.contains("no hello in"));
+
+    // Only the failing pair is aligned again, also when the path is given in another way.
+    align(Path::new("season"))
+        .code(1)
+        .stdout(predicate::str::is_match("unchanged +- +ep01.srt").unwrap());
+    align(&dir.path().join("season"))
+        .code(1)
+        .stdout(predicate::str::is_match("unchanged +- +ep01.srt").unwrap());
+}
+
 #[test]
 fn can_show_confirm_without_panicking() {
     // run commands without the -y switch so the confirm is shown and make sure that